@@ -0,0 +1,68 @@
+//! Measures the throughput of a handler fed by a `BenchLoop`, in the spirit of a criterion
+//! benchmark: a warm-up run, then several timed samples, reported as events per second.
+//!
+//! Run with `cargo run --release --example bench_loop`.
+
+extern crate events_loop;
+
+use std::time::{Duration, Instant};
+
+use events_loop::bench::BenchLoop;
+use events_loop::{ControlFlow, EventsLoop};
+
+const EVENTS: u64 = 1_000_000;
+const SAMPLES: usize = 10;
+
+// Runs `handler` over every event of `schedule`, and returns the time it took.
+fn sample(
+    events_loop: &mut BenchLoop<u64>,
+    schedule: &[u64],
+    handler: &mut dyn FnMut(u64) -> ControlFlow,
+) -> Duration {
+    events_loop.load(schedule.to_vec());
+    let start = Instant::now();
+    events_loop.run(handler);
+    start.elapsed()
+}
+
+fn bench(name: &str, schedule: &[u64], handler: &mut dyn FnMut(u64) -> ControlFlow) {
+    let mut events_loop = BenchLoop::new(Vec::new());
+    sample(&mut events_loop, schedule, handler);
+
+    let mut samples = (0..SAMPLES)
+        .map(|_| sample(&mut events_loop, schedule, handler))
+        .collect::<Vec<_>>();
+    samples.sort();
+    assert_eq!(
+        events_loop.processed(),
+        ((SAMPLES + 1) * schedule.len()) as u64
+    );
+
+    let median = samples[SAMPLES / 2];
+    println!(
+        "{:<12} median {:>10.3?}  min {:>10.3?}  max {:>10.3?}  {:>8.1} Mevents/s",
+        name,
+        median,
+        samples[0],
+        samples[SAMPLES - 1],
+        schedule.len() as f64 / median.as_secs_f64() / 1e6
+    );
+}
+
+fn main() {
+    let schedule = (1..=EVENTS).collect::<Vec<_>>();
+
+    let mut total = 0u64;
+    bench("sum", &schedule, &mut |n| {
+        total = total.wrapping_add(n);
+        ControlFlow::Continue
+    });
+
+    let mut histogram = [0u64; 16];
+    bench("histogram", &schedule, &mut |n| {
+        histogram[(n % 16) as usize] += 1;
+        ControlFlow::Continue
+    });
+
+    println!("checksum {}", total.wrapping_add(histogram[0]));
+}
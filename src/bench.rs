@@ -0,0 +1,120 @@
+//! Feeding a precomputed schedule of events through a loop as fast as possible.
+
+use std::vec;
+
+use {ControlFlow, EventsLoop, EventsLoopClosed, EventsLoopProxy};
+
+/// An `EventsLoop` that delivers a fixed list of events without ever blocking, meant to
+/// benchmark the handlers downstream of it.
+///
+/// Unlike the loops built for tests, it records nothing about the events: it hands them out as
+/// fast as it can and only counts them, see `processed`. `run` delivers every scheduled event
+/// then returns, as if the callback had broken, and `load` schedules the next batch, so that a
+/// benchmark can reuse the same loop and adapters across iterations.
+///
+/// Nothing can wake the loop up: `create_proxy` returns a proxy whose `wakeup` always fails with
+/// `EventsLoopClosed`.
+pub struct BenchLoop<T> {
+    events: vec::IntoIter<T>,
+    processed: u64,
+}
+
+impl<T> BenchLoop<T> {
+    /// Builds a loop that delivers `events`, in order.
+    pub fn new(events: Vec<T>) -> BenchLoop<T> {
+        BenchLoop {
+            events: events.into_iter(),
+            processed: 0,
+        }
+    }
+
+    /// Replaces the events left to deliver with `events`. The processed count keeps running.
+    pub fn load(&mut self, events: Vec<T>) {
+        self.events = events.into_iter();
+    }
+
+    /// Returns the number of events delivered so far.
+    pub fn processed(&self) -> u64 {
+        self.processed
+    }
+
+    /// Returns the number of events left to deliver.
+    pub fn remaining(&self) -> usize {
+        self.events.len()
+    }
+}
+
+impl<T> EventsLoop<T> for BenchLoop<T> {
+    fn poll_events(&mut self, callback: &mut dyn FnMut(T)) {
+        let mut processed = 0;
+        for event in &mut self.events {
+            processed += 1;
+            callback(event);
+        }
+        self.processed += processed;
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(T) -> ControlFlow) {
+        let mut processed = 0;
+        for event in &mut self.events {
+            processed += 1;
+            if callback(event).is_exit() {
+                break;
+            }
+        }
+        self.processed += processed;
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(ClosedProxy)
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        Some(self.events.len())
+    }
+}
+
+struct ClosedProxy;
+
+impl EventsLoopProxy for ClosedProxy {
+    fn wakeup(&self) -> Result<(), EventsLoopClosed> {
+        Err(EventsLoopClosed)
+    }
+
+    fn clone(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(ClosedProxy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BenchLoop;
+    use {ControlFlow, EventsLoop};
+
+    #[test]
+    fn counts_the_delivered_events() {
+        let mut l = BenchLoop::new((0..100).collect());
+        let mut sum = 0;
+        l.run(&mut |n| {
+            sum += n;
+            if n == 49 {
+                ControlFlow::Break
+            } else {
+                ControlFlow::Continue
+            }
+        });
+        assert_eq!((l.processed(), l.remaining()), (50, 50));
+
+        l.run(&mut |n| {
+            sum += n;
+            ControlFlow::Continue
+        });
+        assert_eq!(sum, (0..100).sum());
+        assert_eq!(l.processed(), 100);
+
+        l.load(vec![1, 2]);
+        l.poll_events(&mut |_| ());
+        assert_eq!(l.processed(), 102);
+        assert!(l.create_proxy().wakeup().is_err());
+    }
+}
@@ -0,0 +1,624 @@
+//! An in-memory `EventsLoop` with a fixed capacity.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+
+use closed::ClosedCallbacks;
+use {
+    ControlFlow, DropSink, EventsLoop, EventsLoopClosed, EventsLoopPayloadProxy, EventsLoopProxy,
+    FromWakeup, NotSendSync, OverflowPolicy, RunningFlag, WakeupError,
+};
+
+struct Queue<T> {
+    events: VecDeque<T>,
+    // Set by the wakeups that found the queue full.
+    wakeup: bool,
+    // The events discarded since the loop last reported them, if it has a drop sink.
+    discarded: Vec<T>,
+}
+
+impl<T> Queue<T> {
+    fn len(&self) -> usize {
+        self.events.len() + self.wakeup as usize
+    }
+}
+
+struct Shared<T> {
+    queue: Mutex<Queue<T>>,
+    available: Condvar,
+    capacity: usize,
+    policy: OverflowPolicy,
+    dropped: AtomicUsize,
+    keep_discarded: AtomicBool,
+    // The number of live proxies.
+    producers: AtomicUsize,
+    closed: ClosedCallbacks,
+}
+
+impl<T> Shared<T> {
+    // Queues a wakeup if there is room, or records it outside of the queue otherwise.
+    fn wake(&self)
+    where
+        T: FromWakeup,
+    {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.events.len() < self.capacity {
+            queue.events.push_back(T::from_wakeup());
+        } else {
+            queue.wakeup = true;
+        }
+        self.available.notify_one();
+    }
+
+    fn discard(&self, queue: &mut Queue<T>, event: T) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        if self.keep_discarded.load(Ordering::Relaxed) {
+            queue.discarded.push(event);
+        }
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        self.closed.close();
+    }
+}
+
+/// An `EventsLoop` whose events are sent by other threads through a `BoundedProxy`, and stored
+/// in a queue of fixed capacity.
+///
+/// When the queue is full, the `OverflowPolicy` given to `with_capacity` decides what happens to
+/// a new event: `DropOldest` discards the oldest queued event, `DropNewest` discards the new one,
+/// and `Reject` hands the new one back to the producer with `WakeupError::Full`. The events that
+/// were discarded are counted, see `dropped`. Memory usage thus stays bounded however much the
+/// producers outpace the loop.
+///
+/// The wakeups sent through `EventsLoopProxy::wakeup` are never discarded, and never make room
+/// by discarding an event: the ones that find the queue full are recorded in a flag outside of
+/// it, and coalesced into a single awakened event delivered after the queued events.
+///
+/// Once every proxy is gone and the queue is empty, the loop reports itself closed through
+/// `EventsLoop::is_closed`, and `run` returns, since nothing can send it an event anymore. A
+/// proxy created afterwards reopens the loop.
+pub struct BoundedEventsLoop<T> {
+    shared: Arc<Shared<T>>,
+    drop_sink: Option<DropSink<T>>,
+    running: RunningFlag,
+    _not_send_sync: NotSendSync,
+}
+
+impl<T> BoundedEventsLoop<T> {
+    /// Builds a loop that holds at most `capacity` pending events, applying `policy` beyond.
+    pub fn with_capacity(capacity: usize, policy: OverflowPolicy) -> BoundedEventsLoop<T> {
+        BoundedEventsLoop {
+            shared: Arc::new(Shared {
+                queue: Mutex::new(Queue {
+                    events: VecDeque::with_capacity(capacity),
+                    wakeup: false,
+                    discarded: Vec::new(),
+                }),
+                available: Condvar::new(),
+                capacity,
+                policy,
+                dropped: AtomicUsize::new(0),
+                keep_discarded: AtomicBool::new(false),
+                producers: AtomicUsize::new(0),
+                closed: ClosedCallbacks::new(),
+            }),
+            drop_sink: None,
+            running: RunningFlag::default(),
+            _not_send_sync: NotSendSync::new(),
+        }
+    }
+
+    /// Reports the events discarded on overflow to `sink`.
+    ///
+    /// The events are discarded on the threads of the producers, and the sink can only run on
+    /// the thread of the loop, so the discarded events are set aside, and reported the next time
+    /// the loop polls or receives an event. Until then, they take up memory beyond the capacity.
+    pub fn with_drop_sink(mut self, sink: DropSink<T>) -> BoundedEventsLoop<T> {
+        self.shared.keep_discarded.store(true, Ordering::Relaxed);
+        self.drop_sink = Some(sink);
+        self
+    }
+
+    /// Creates a proxy that can send events to the loop from another thread.
+    pub fn create_payload_proxy(&self) -> BoundedProxy<T> {
+        self.shared.producers.fetch_add(1, Ordering::SeqCst);
+        BoundedProxy {
+            shared: Arc::downgrade(&self.shared),
+        }
+    }
+
+    /// Returns the maximum number of pending events.
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// Returns the number of pending events.
+    pub fn len(&self) -> usize {
+        self.shared.queue.lock().unwrap().len()
+    }
+
+    /// Returns `true` if no events are pending.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of events that were discarded because the queue was full.
+    ///
+    /// Events refused with `OverflowPolicy::Reject` are handed back to their producer, and as
+    /// such are not counted.
+    pub fn dropped(&self) -> usize {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+}
+
+fn report<T>(drop_sink: &Option<DropSink<T>>, discarded: Vec<T>) {
+    if let Some(ref drop_sink) = *drop_sink {
+        for event in discarded {
+            (*drop_sink.borrow_mut())(event);
+        }
+    }
+}
+
+impl<T> EventsLoop<T> for BoundedEventsLoop<T>
+where
+    T: FromWakeup + Send + 'static,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(T)) {
+        let _running = self.running.enter();
+        let (pending, wakeup, discarded) = {
+            let mut queue = self.shared.queue.lock().unwrap();
+            let capacity = queue.events.capacity();
+            (
+                std::mem::replace(&mut queue.events, VecDeque::with_capacity(capacity)),
+                std::mem::replace(&mut queue.wakeup, false),
+                std::mem::take(&mut queue.discarded),
+            )
+        };
+        report(&self.drop_sink, discarded);
+        for event in pending {
+            callback(event);
+        }
+        if wakeup {
+            callback(T::from_wakeup());
+        }
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(T) -> ControlFlow) {
+        let _running = self.running.enter();
+        loop {
+            let (event, discarded) = {
+                let mut queue = self.shared.queue.lock().unwrap();
+                loop {
+                    if let Some(event) = queue.events.pop_front() {
+                        break (Some(event), std::mem::take(&mut queue.discarded));
+                    }
+                    if queue.wakeup {
+                        queue.wakeup = false;
+                        break (Some(T::from_wakeup()), std::mem::take(&mut queue.discarded));
+                    }
+                    if self.shared.producers.load(Ordering::SeqCst) == 0 {
+                        break (None, std::mem::take(&mut queue.discarded));
+                    }
+                    queue = self.shared.available.wait(queue).unwrap();
+                }
+            };
+            report(&self.drop_sink, discarded);
+            match event {
+                Some(event) => {
+                    if callback(event).is_exit() {
+                        return;
+                    }
+                }
+                None => return,
+            }
+        }
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(self.create_payload_proxy())
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(WakeupProxy {
+            shared: Arc::downgrade(&self.shared),
+        })
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        Some(self.len())
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        let queued = self.shared.queue.lock().unwrap().events.len();
+        Some(self.capacity().saturating_sub(queued))
+    }
+
+    /// Returns `true` once every proxy is gone and the queue is empty. A proxy created
+    /// afterwards reopens the loop.
+    fn is_closed(&self) -> bool {
+        // Checked under the lock, so that the last event of a proxy is seen before its drop.
+        let queue = self.shared.queue.lock().unwrap();
+        self.shared.producers.load(Ordering::SeqCst) == 0 && queue.len() == 0
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.get()
+    }
+}
+
+/// Sends events to a `BoundedEventsLoop` from any thread.
+pub struct BoundedProxy<T> {
+    shared: Weak<Shared<T>>,
+}
+
+impl<T> Clone for BoundedProxy<T> {
+    fn clone(&self) -> BoundedProxy<T> {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.producers.fetch_add(1, Ordering::SeqCst);
+        }
+        BoundedProxy {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for BoundedProxy<T> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.shared.upgrade() {
+            // Under the lock, so that a `run` about to wait sees the count drop.
+            let _queue = shared.queue.lock().unwrap();
+            if shared.producers.fetch_sub(1, Ordering::SeqCst) == 1 {
+                shared.available.notify_all();
+            }
+        }
+    }
+}
+
+impl<T> BoundedProxy<T> {
+    /// Registers `f` to run once the loop is closed.
+    ///
+    /// `f` runs at most once, on the thread that drops the loop, or on the thread of a proxy
+    /// that was sending an event to it at that moment. If the loop is already closed, `f` runs
+    /// right away on the calling thread. Callbacks registered through any proxy of the same loop
+    /// run in the order they were registered.
+    pub fn on_closed<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        match self.shared.upgrade() {
+            Some(shared) => shared.closed.register(Box::new(f)),
+            None => f(),
+        }
+    }
+
+    /// Sends `event` to the loop, applying its overflow policy if the queue is full.
+    ///
+    /// Returns `WakeupError::Closed` if the loop no longer exists, and `WakeupError::Full` if the
+    /// queue is full and the policy is `OverflowPolicy::Reject`.
+    pub fn wakeup_with(&self, event: T) -> Result<(), WakeupError<T>> {
+        let shared = match self.shared.upgrade() {
+            Some(shared) => shared,
+            None => return Err(WakeupError::Closed(event)),
+        };
+
+        let mut queue = shared.queue.lock().unwrap();
+        if queue.events.len() >= shared.capacity {
+            match shared.policy {
+                OverflowPolicy::Reject => return Err(WakeupError::Full(event)),
+                OverflowPolicy::DropNewest => {
+                    shared.discard(&mut queue, event);
+                    return Ok(());
+                }
+                OverflowPolicy::DropOldest => match queue.events.pop_front() {
+                    Some(oldest) => shared.discard(&mut queue, oldest),
+                    None => {
+                        // A capacity of zero can't hold anything.
+                        shared.discard(&mut queue, event);
+                        return Ok(());
+                    }
+                },
+            }
+        }
+        queue.events.push_back(event);
+        shared.available.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> EventsLoopProxy for BoundedProxy<T>
+where
+    T: FromWakeup + Send + 'static,
+{
+    /// Sends the event produced by `FromWakeup::from_wakeup`.
+    ///
+    /// If the queue is full, the overflow policy doesn't apply: the wakeup is recorded outside
+    /// of the queue instead, merged with the other wakeups recorded this way.
+    fn wakeup(&self) -> Result<(), EventsLoopClosed> {
+        let shared = self.shared.upgrade().ok_or(EventsLoopClosed)?;
+        shared.wake();
+        Ok(())
+    }
+
+    fn clone(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(Clone::clone(self))
+    }
+
+    /// Returns the number of queued events, or `None` if the loop no longer exists.
+    fn pending_wakeups(&self) -> Option<usize> {
+        self.shared
+            .upgrade()
+            .map(|shared| shared.queue.lock().unwrap().len())
+    }
+}
+
+impl<T> EventsLoopPayloadProxy<T> for BoundedProxy<T>
+where
+    T: FromWakeup + Send + 'static,
+{
+    fn wakeup_with(&self, event: T) -> Result<(), WakeupError<T>> {
+        BoundedProxy::wakeup_with(self, event)
+    }
+}
+
+// Wakes up a `BoundedEventsLoop` without counting as one of its producers, so its wakeups fail
+// while none of them is live.
+struct WakeupProxy<T> {
+    shared: Weak<Shared<T>>,
+}
+
+impl<T> EventsLoopProxy for WakeupProxy<T>
+where
+    T: FromWakeup + Send + 'static,
+{
+    fn wakeup(&self) -> Result<(), EventsLoopClosed> {
+        let shared = self.shared.upgrade().ok_or(EventsLoopClosed)?;
+        if shared.producers.load(Ordering::SeqCst) == 0 {
+            return Err(EventsLoopClosed);
+        }
+        shared.wake();
+        Ok(())
+    }
+
+    fn clone(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(WakeupProxy {
+            shared: self.shared.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::BoundedEventsLoop;
+    use heartbeat::Heartbeat;
+    use mock::Event;
+    use {BufferStatus, ControlFlow, EventsLoop, EventsLoopProxy, OverflowPolicy, WakeupError};
+
+    // Sends three events to a loop of capacity two, and returns what comes out of it, the result
+    // of the third send and the drop count.
+    fn overflow(policy: OverflowPolicy) -> (Vec<Event>, Result<(), WakeupError<Event>>, usize) {
+        let mut l = BoundedEventsLoop::with_capacity(2, policy);
+        let proxy = l.create_payload_proxy();
+        proxy.wakeup_with(Event::Data(1)).unwrap();
+        proxy.wakeup_with(Event::Data(2)).unwrap();
+        let third = proxy.wakeup_with(Event::Data(3));
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |e| delivered.push(e));
+        (delivered, third, l.dropped())
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_latest_events() {
+        let (delivered, third, dropped) = overflow(OverflowPolicy::DropOldest);
+        assert_eq!(delivered, vec![Event::Data(2), Event::Data(3)]);
+        assert_eq!(third, Ok(()));
+        assert_eq!(dropped, 1);
+    }
+
+    #[test]
+    fn drop_newest_keeps_the_first_events() {
+        let (delivered, third, dropped) = overflow(OverflowPolicy::DropNewest);
+        assert_eq!(delivered, vec![Event::Data(1), Event::Data(2)]);
+        assert_eq!(third, Ok(()));
+        assert_eq!(dropped, 1);
+    }
+
+    #[test]
+    fn reject_hands_the_event_back() {
+        let (delivered, third, dropped) = overflow(OverflowPolicy::Reject);
+        assert_eq!(delivered, vec![Event::Data(1), Event::Data(2)]);
+        assert_eq!(third, Err(WakeupError::Full(Event::Data(3))));
+        assert_eq!(dropped, 0);
+    }
+
+    #[test]
+    fn drops_are_reported_to_the_sink() {
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let sink = dropped.clone();
+        let mut l = BoundedEventsLoop::with_capacity(2, OverflowPolicy::DropOldest)
+            .with_drop_sink(Rc::new(RefCell::new(move |e| sink.borrow_mut().push(e))));
+        let proxy = l.create_payload_proxy();
+        let producer = thread::spawn(move || {
+            for n in 0..5 {
+                proxy.wakeup_with(Event::Data(n)).unwrap();
+            }
+        });
+        producer.join().unwrap();
+        assert!(dropped.borrow().is_empty());
+
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered, vec![Event::Data(3), Event::Data(4)]);
+        assert_eq!(
+            *dropped.borrow(),
+            (0..3).map(Event::Data).collect::<Vec<_>>()
+        );
+        assert_eq!(l.dropped(), 3);
+    }
+
+    #[test]
+    fn wakeups_survive_a_full_queue() {
+        for &policy in &[
+            OverflowPolicy::DropOldest,
+            OverflowPolicy::DropNewest,
+            OverflowPolicy::Reject,
+        ] {
+            let mut l = BoundedEventsLoop::with_capacity(1, policy);
+            let proxy = l.create_payload_proxy();
+            proxy.wakeup_with(Event::Data(1)).unwrap();
+            proxy.wakeup().unwrap();
+            proxy.wakeup().unwrap();
+            assert_eq!(l.len(), 2);
+            assert_eq!(l.remaining_capacity(), Some(0));
+
+            let mut delivered = Vec::new();
+            l.poll_events(&mut |e| delivered.push(e));
+            assert_eq!(delivered, vec![Event::Data(1), Event::Awakened]);
+            assert_eq!(l.dropped(), 0);
+        }
+    }
+
+    #[test]
+    fn run_receives_events_from_other_threads() {
+        let mut l = BoundedEventsLoop::with_capacity(4, OverflowPolicy::Reject);
+        let proxy = l.create_payload_proxy();
+        let producer = thread::spawn(move || {
+            for n in 0..10 {
+                let mut event = Event::Data(n);
+                while let Err(WakeupError::Full(back)) = proxy.wakeup_with(event) {
+                    event = back;
+                    thread::yield_now();
+                }
+            }
+            proxy.wakeup().unwrap();
+        });
+
+        let mut delivered = Vec::new();
+        l.run(&mut |e| match e {
+            Event::Awakened => ControlFlow::Break,
+            Event::Data(n) => {
+                delivered.push(n);
+                ControlFlow::Continue
+            }
+        });
+        producer.join().unwrap();
+        assert_eq!(delivered, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn proxy_counts_pending_wakeups() {
+        let mut l = BoundedEventsLoop::<Event>::with_capacity(8, OverflowPolicy::Reject);
+        let proxy = l.create_proxy();
+        for _ in 0..3 {
+            proxy.wakeup().unwrap();
+        }
+        assert_eq!(proxy.pending_wakeups(), Some(3));
+
+        l.poll_events(&mut |_| ());
+        assert_eq!(proxy.pending_wakeups(), Some(0));
+    }
+
+    #[test]
+    fn poll_reports_the_remaining_capacity() {
+        let mut l = BoundedEventsLoop::with_capacity(4, OverflowPolicy::Reject);
+        let proxy = l.create_payload_proxy();
+        for n in 0..3 {
+            proxy.wakeup_with(Event::Data(n)).unwrap();
+        }
+        assert_eq!(l.remaining_capacity(), Some(1));
+
+        let mut delivered = Vec::new();
+        let status = l.poll_events_with_feedback(&mut |e| {
+            delivered.push(e);
+            if delivered.len() == 1 {
+                // Arrives during the drain, so it is still pending afterwards.
+                proxy.wakeup_with(Event::Data(10)).unwrap();
+            }
+        });
+        assert_eq!(delivered.len(), 3);
+        assert_eq!(
+            status,
+            BufferStatus {
+                drained: 3,
+                remaining_capacity: Some(3),
+            }
+        );
+    }
+
+    #[test]
+    fn adapters_report_the_remaining_capacity() {
+        let l = BoundedEventsLoop::with_capacity(4, OverflowPolicy::Reject);
+        let proxy = l.create_payload_proxy();
+        let mut l = Heartbeat::new(l);
+        proxy.wakeup_with(Event::Data(1)).unwrap();
+        assert_eq!(l.remaining_capacity(), Some(3));
+
+        let status = l.poll_events_with_feedback(&mut |_| ());
+        assert_eq!(
+            status,
+            BufferStatus {
+                drained: 1,
+                remaining_capacity: Some(4),
+            }
+        );
+    }
+
+    #[test]
+    fn wakeup_proxies_dont_keep_the_loop_open() {
+        let mut l = BoundedEventsLoop::<Event>::with_capacity(2, OverflowPolicy::Reject);
+        let proxy = l.create_payload_proxy();
+        let wakeup = l.create_wakeup_proxy();
+        wakeup.wakeup().unwrap();
+        drop(proxy);
+
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered, vec![Event::Awakened]);
+        assert!(l.is_closed());
+        assert!(wakeup.wakeup().is_err());
+    }
+
+    #[test]
+    fn closes_once_the_proxies_are_gone_and_the_queue_is_drained() {
+        let mut l = BoundedEventsLoop::with_capacity(2, OverflowPolicy::Reject);
+        let proxy = l.create_payload_proxy();
+        let clone = EventsLoopProxy::clone(&proxy);
+        proxy.wakeup_with(Event::Data(1)).unwrap();
+        drop(proxy);
+        drop(clone);
+        assert!(!l.is_closed());
+
+        l.poll_events(&mut |_| ());
+        assert!(l.is_closed());
+    }
+
+    #[test]
+    fn proxy_reports_closed_loop() {
+        let l = BoundedEventsLoop::<Event>::with_capacity(1, OverflowPolicy::DropOldest);
+        let proxy = l.create_proxy();
+        drop(l);
+        assert!(proxy.wakeup().is_err());
+    }
+
+    #[test]
+    fn on_closed_runs_once_the_loop_is_dropped() {
+        let l = BoundedEventsLoop::<Event>::with_capacity(1, OverflowPolicy::DropOldest);
+        let proxy = l.create_payload_proxy();
+        let (sender, receiver) = mpsc::channel();
+        let early = sender.clone();
+        proxy.on_closed(move || early.send("early").unwrap());
+        assert!(receiver.try_recv().is_err());
+
+        drop(l);
+        proxy.on_closed(move || sender.send("late").unwrap());
+        assert_eq!(receiver.iter().collect::<Vec<_>>(), vec!["early", "late"]);
+    }
+}
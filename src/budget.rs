@@ -0,0 +1,156 @@
+//! A memory budget shared by the adapters that buffer events.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// A number of bytes that several buffering adapters draw from, so that the memory they use
+/// together stays bounded, whatever the share of each.
+///
+/// The adapters that accept a budget, with `with_memory_budget`, estimate the size of every event
+/// before buffering it, and release it once the event leaves the buffer. When the budget can't
+/// cover a new event, the adapter handles it as if its buffer were full, applying its
+/// `OverflowPolicy`: `DropOldest` discards its own oldest events until the new one fits, or
+/// until it has none left, and the other policies discard the new event. The discarded events
+/// are reported to the `DropSink` of the adapter, as usual. `reorder::Reorder`, which never
+/// discards events, releases its oldest held events early instead. The buffer capacity of each
+/// adapter still applies on top of the budget.
+///
+/// The size of an event is given by the estimator passed to `new`. It is called once when the
+/// event is buffered and once when it leaves the buffer, so it must return the same size for
+/// the same event every time, or the budget drifts. It needn't be exact, for example
+/// `mem::size_of` plus the length of the heap data of the event, but it runs on every event, so
+/// it must be cheap.
+///
+/// Clones share the same remaining bytes. The budget isn't `Send`: the adapters that share it
+/// live on the thread of the loop.
+pub struct MemoryBudget<Event> {
+    remaining: Rc<Cell<usize>>,
+    estimate: Rc<dyn Fn(&Event) -> usize>,
+}
+
+impl<Event> Clone for MemoryBudget<Event> {
+    fn clone(&self) -> MemoryBudget<Event> {
+        MemoryBudget {
+            remaining: self.remaining.clone(),
+            estimate: self.estimate.clone(),
+        }
+    }
+}
+
+impl<Event> MemoryBudget<Event> {
+    /// Builds a budget of `bytes`, measuring the events with `estimate`.
+    pub fn new<F>(bytes: usize, estimate: F) -> MemoryBudget<Event>
+    where
+        F: Fn(&Event) -> usize + 'static,
+    {
+        MemoryBudget {
+            remaining: Rc::new(Cell::new(bytes)),
+            estimate: Rc::new(estimate),
+        }
+    }
+
+    /// Returns the number of bytes left.
+    pub fn remaining(&self) -> usize {
+        self.remaining.get()
+    }
+
+    pub(crate) fn size_of(&self, event: &Event) -> usize {
+        (self.estimate)(event)
+    }
+
+    // Takes `bytes` out of the budget, if there are enough left.
+    pub(crate) fn reserve(&self, bytes: usize) -> bool {
+        match self.remaining.get().checked_sub(bytes) {
+            Some(left) => {
+                self.remaining.set(left);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn release(&self, bytes: usize) {
+        self.remaining.set(self.remaining.get() + bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::MemoryBudget;
+    use gate::Gate;
+    use mock::MockEventsLoop;
+    use {EventsLoop, OverflowPolicy};
+
+    #[test]
+    fn adapters_share_one_budget() {
+        let budget = MemoryBudget::new(100, |_: &u32| 10);
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let sink = dropped.clone();
+
+        let mut first =
+            Gate::new(MockEventsLoop::new((0..6).collect())).with_memory_budget(budget.clone());
+        let mut second = Gate::new(MockEventsLoop::new((10..16).collect()))
+            .with_capacity(16, OverflowPolicy::DropNewest)
+            .with_drop_sink(Rc::new(RefCell::new(move |e| sink.borrow_mut().push(e))))
+            .with_memory_budget(budget.clone());
+        first.handle().pause();
+        second.handle().pause();
+
+        first.poll_events(&mut |_| panic!("the gate is paused"));
+        assert_eq!(budget.remaining(), 40);
+        second.poll_events(&mut |_| panic!("the gate is paused"));
+        assert_eq!(budget.remaining(), 0);
+        assert_eq!(second.buffered(), 4);
+        assert_eq!(*dropped.borrow(), vec![14, 15]);
+
+        first.handle().resume();
+        let mut delivered = Vec::new();
+        first.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered, (0..6).collect::<Vec<_>>());
+        assert_eq!(budget.remaining(), 60);
+
+        drop(second);
+        assert_eq!(budget.remaining(), 100);
+    }
+
+    #[test]
+    fn drop_oldest_makes_room_within_the_budget() {
+        let budget = MemoryBudget::new(25, |&n: &u32| n as usize);
+        let mut gate =
+            Gate::new(MockEventsLoop::new(vec![10, 10, 10, 20])).with_memory_budget(budget.clone());
+        gate.handle().pause();
+        gate.poll_events(&mut |_| ());
+        assert_eq!(gate.buffered(), 1);
+        assert_eq!(budget.remaining(), 5);
+
+        // An event larger than the whole budget can't be buffered at all.
+        let mut gate = Gate::new(MockEventsLoop::new(vec![30])).with_memory_budget(budget.clone());
+        gate.handle().pause();
+        gate.poll_events(&mut |_| ());
+        assert_eq!(gate.buffered(), 0);
+        assert_eq!(budget.remaining(), 5);
+    }
+
+    #[test]
+    fn a_late_budget_covers_the_buffered_events() {
+        let budget = MemoryBudget::new(25, |_: &u32| 10);
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let sink = dropped.clone();
+        let mut gate = Gate::new(MockEventsLoop::new((0..4).collect()))
+            .with_drop_sink(Rc::new(RefCell::new(move |e| sink.borrow_mut().push(e))));
+        gate.handle().pause();
+        gate.poll_events(&mut |_| ());
+        assert_eq!(gate.buffered(), 4);
+
+        let gate = gate.with_memory_budget(budget.clone());
+        assert_eq!(gate.buffered(), 2);
+        assert_eq!(*dropped.borrow(), vec![0, 1]);
+        assert_eq!(budget.remaining(), 5);
+
+        drop(gate);
+        assert_eq!(budget.remaining(), 25);
+    }
+}
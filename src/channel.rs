@@ -0,0 +1,644 @@
+//! An `EventsLoop` backed by a `std::sync::mpsc` channel, and a bridge giving a `Sender` to any
+//! other loop.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use closed::ClosedCallbacks;
+use {
+    ControlFlow, EventsLoop, EventsLoopClosed, EventsLoopPayloadProxy, EventsLoopProxy, FromWakeup,
+    NotSendSync, RunningFlag, WakeupError,
+};
+
+/// An `EventsLoop` whose events are sent by other threads through an unbounded
+/// `std::sync::mpsc` channel.
+///
+/// Its proxies hand out the `Sender` of the channel through
+/// `EventsLoopPayloadProxy::as_sender`, so code that only knows about `mpsc::Sender` can feed the
+/// loop without any extra thread.
+///
+/// The proxies count the events they have sent and that the loop hasn't received yet, see
+/// `EventsLoopProxy::pending_wakeups`. The events sent directly through the `Sender` are not
+/// counted, and make the count an underestimate.
+///
+/// The proxies of `create_proxy_coalesced` collapse the wakeups sent while one is already
+/// pending into that one, instead of queueing each of them.
+///
+/// The loop also counts its live proxies. Once none is left and every event they sent has been
+/// received, it reports itself closed through `EventsLoop::is_closed`, and `run` returns, since
+/// nothing can send it an event anymore. A proxy created afterwards reopens the loop. A `Sender`
+/// handed out through `EventsLoopPayloadProxy::as_sender` can't be tracked, so from then on the
+/// loop never closes.
+pub struct ChannelEventsLoop<T> {
+    // Replaced when a proxy reopens the loop.
+    receiver: RefCell<Receiver<T>>,
+    pending: Arc<AtomicUsize>,
+    producers: Arc<Mutex<Producers<T>>>,
+    plain_wakeups: Arc<AtomicUsize>,
+    wakeup_pending: Arc<AtomicBool>,
+    closed: Arc<ClosedCallbacks>,
+    running: RunningFlag,
+    _not_send_sync: NotSendSync,
+}
+
+impl<T> ChannelEventsLoop<T> {
+    /// Builds a loop with an empty channel.
+    pub fn new() -> ChannelEventsLoop<T> {
+        // The channel is connected by the first proxy.
+        let (_, receiver) = mpsc::channel();
+        ChannelEventsLoop {
+            receiver: RefCell::new(receiver),
+            pending: Arc::new(AtomicUsize::new(0)),
+            producers: Arc::new(Mutex::new(Producers {
+                live: 0,
+                untracked: false,
+                sender: None,
+            })),
+            plain_wakeups: Arc::new(AtomicUsize::new(0)),
+            wakeup_pending: Arc::new(AtomicBool::new(false)),
+            closed: Arc::new(ClosedCallbacks::new()),
+            running: RunningFlag::default(),
+            _not_send_sync: NotSendSync::new(),
+        }
+    }
+
+    /// Builds a loop whose channel already holds `events`, in order.
+    ///
+    /// Along with `drain_state`, this lets the pending events survive a reload of the code that
+    /// handles them: drain the old loop, and hand its events to the new one.
+    pub fn with_initial_events(events: Vec<T>) -> ChannelEventsLoop<T> {
+        let l = ChannelEventsLoop::new();
+        let (sender, receiver) = mpsc::channel();
+        l.pending.fetch_add(events.len(), Ordering::Relaxed);
+        for event in events {
+            // The receiver is still around, so the channel can't be disconnected.
+            let _ = sender.send(event);
+        }
+        l.receiver.replace(receiver);
+        l
+    }
+
+    /// Takes every pending event out of the channel, in order, without delivering them.
+    ///
+    /// The events sent after the call stay in the channel; drop the proxies first to make sure
+    /// nothing is left behind.
+    pub fn drain_state(&mut self) -> Vec<T>
+    where
+        T: FromWakeup,
+    {
+        let mut events = Vec::new();
+        while let Ok(event) = self.receiver.get_mut().try_recv() {
+            self.received(&event);
+            events.push(event);
+        }
+        events
+    }
+
+    /// Creates a proxy that can send events to the loop from another thread.
+    pub fn create_payload_proxy(&self) -> ChannelProxy<T> {
+        let mut producers = self.producers.lock().unwrap();
+        let sender = match producers.sender {
+            Some(ref sender) => sender.clone(),
+            None => {
+                // The previous channel is disconnected: move what is left of it to a new one.
+                let (sender, receiver) = mpsc::channel();
+                for event in self.receiver.replace(receiver).try_iter() {
+                    let _ = sender.send(event);
+                }
+                producers.sender = Some(sender.clone());
+                sender
+            }
+        };
+        producers.live += 1;
+        ChannelProxy {
+            sender,
+            pending: self.pending.clone(),
+            producers: self.producers.clone(),
+            plain_wakeups: self.plain_wakeups.clone(),
+            closed: self.closed.clone(),
+        }
+    }
+
+    /// Creates a proxy whose wakeups are coalesced.
+    ///
+    /// A wakeup sent through such a proxy while another one is still pending in the channel is
+    /// merged into it, so that however many threads hammer `wakeup`, the loop sees at most one
+    /// awakened event per drain. The flag is cleared as soon as the loop receives the coalesced
+    /// wakeup, before it reaches the callback, so a wakeup sent while the callback runs isn't
+    /// lost. Payload events are never coalesced.
+    ///
+    /// To tell the coalesced wakeup apart, the loop counts the wakeup events sent through the
+    /// other proxies, and takes the first one it receives beyond that count for the coalesced
+    /// one. The wakeup events sent directly through a `Sender` aren't counted, and can clear the
+    /// flag early, which lets a duplicate through.
+    pub fn create_proxy_coalesced(&self) -> CoalescedProxy<T> {
+        CoalescedProxy {
+            proxy: self.create_payload_proxy(),
+            wakeup_pending: self.wakeup_pending.clone(),
+        }
+    }
+
+    // Accounts for an event taken out of the channel.
+    fn received(&self, event: &T)
+    where
+        T: FromWakeup,
+    {
+        let _ = self
+            .pending
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+        if event.is_wakeup()
+            && self
+                .plain_wakeups
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_err()
+        {
+            self.wakeup_pending.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Runs the callbacks registered with `ChannelProxy::on_closed`.
+impl<T> Drop for ChannelEventsLoop<T> {
+    fn drop(&mut self) {
+        self.closed.close();
+    }
+}
+
+impl<T> Default for ChannelEventsLoop<T> {
+    fn default() -> ChannelEventsLoop<T> {
+        ChannelEventsLoop::new()
+    }
+}
+
+impl<T> EventsLoop<T> for ChannelEventsLoop<T>
+where
+    T: FromWakeup + Send + 'static,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(T)) {
+        let _running = self.running.enter();
+        while let Ok(event) = self.receiver.get_mut().try_recv() {
+            self.received(&event);
+            callback(event);
+        }
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(T) -> ControlFlow) {
+        let _running = self.running.enter();
+        // Fails once the channel is disconnected, which means the loop is closed.
+        while let Ok(event) = self.receiver.get_mut().recv() {
+            self.received(&event);
+            if callback(event).is_exit() {
+                return;
+            }
+        }
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(self.create_payload_proxy())
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(WakeupProxy {
+            pending: self.pending.clone(),
+            producers: self.producers.clone(),
+            plain_wakeups: self.plain_wakeups.clone(),
+        })
+    }
+
+    /// Returns the count of `EventsLoopProxy::pending_wakeups`, which misses the events sent
+    /// directly through the `Sender`.
+    fn pending_count(&self) -> Option<usize> {
+        Some(self.pending.load(Ordering::Relaxed))
+    }
+
+    /// Returns `true` once every proxy is gone and their events have been received. A proxy
+    /// created afterwards reopens the loop.
+    fn is_closed(&self) -> bool {
+        let producers = self.producers.lock().unwrap();
+        producers.live == 0 && !producers.untracked && self.pending.load(Ordering::SeqCst) == 0
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.get()
+    }
+}
+
+// The producers of a `ChannelEventsLoop`, counted by its proxies.
+struct Producers<T> {
+    live: usize,
+    // Set once a `Sender` has been handed out.
+    untracked: bool,
+    // Creates the `Sender` of new proxies. Dropped along with the last proxy, unless a `Sender`
+    // was handed out, so that the channel gets disconnected.
+    sender: Option<Sender<T>>,
+}
+
+/// Sends events to a `ChannelEventsLoop` from any thread.
+pub struct ChannelProxy<T> {
+    sender: Sender<T>,
+    pending: Arc<AtomicUsize>,
+    producers: Arc<Mutex<Producers<T>>>,
+    plain_wakeups: Arc<AtomicUsize>,
+    closed: Arc<ClosedCallbacks>,
+}
+
+impl<T> Clone for ChannelProxy<T> {
+    fn clone(&self) -> ChannelProxy<T> {
+        self.producers.lock().unwrap().live += 1;
+        ChannelProxy {
+            sender: self.sender.clone(),
+            pending: self.pending.clone(),
+            producers: self.producers.clone(),
+            plain_wakeups: self.plain_wakeups.clone(),
+            closed: self.closed.clone(),
+        }
+    }
+}
+
+impl<T> Drop for ChannelProxy<T> {
+    fn drop(&mut self) {
+        let mut producers = self.producers.lock().unwrap();
+        producers.live -= 1;
+        if producers.live == 0 && !producers.untracked {
+            producers.sender = None;
+        }
+    }
+}
+
+impl<T> ChannelProxy<T> {
+    /// Registers `f` to run once the loop is closed.
+    ///
+    /// `f` runs at most once, on the thread that drops the loop, while it is being dropped and
+    /// before its channel is torn down, so before any `wakeup` fails. If the loop is already closed,
+    /// `f` runs right away on the calling thread. Callbacks registered through any proxy of the
+    /// same loop run in the order they were registered. This lets the producers release the
+    /// resources tied to the lifetime of the loop.
+    pub fn on_closed<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.closed.register(Box::new(f));
+    }
+
+    // Sends `event`, counting it among the plain wakeups unless it comes from a coalesced proxy.
+    fn send(&self, event: T, coalesced: bool) -> Result<(), WakeupError<T>>
+    where
+        T: FromWakeup,
+    {
+        let plain = !coalesced && event.is_wakeup();
+        // Counted before sending, so that the loop never receives an event it doesn't know of.
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        if plain {
+            self.plain_wakeups.fetch_add(1, Ordering::SeqCst);
+        }
+        self.sender.send(event).map_err(|mpsc::SendError(event)| {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            if plain {
+                self.plain_wakeups.fetch_sub(1, Ordering::SeqCst);
+            }
+            WakeupError::Closed(event)
+        })
+    }
+}
+
+impl<T> EventsLoopProxy for ChannelProxy<T>
+where
+    T: FromWakeup + Send + 'static,
+{
+    fn wakeup(&self) -> Result<(), EventsLoopClosed> {
+        self.wakeup_with(T::from_wakeup())
+            .map_err(|_| EventsLoopClosed)
+    }
+
+    fn clone(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn pending_wakeups(&self) -> Option<usize> {
+        Some(self.pending.load(Ordering::Relaxed))
+    }
+}
+
+impl<T> EventsLoopPayloadProxy<T> for ChannelProxy<T>
+where
+    T: FromWakeup + Send + 'static,
+{
+    fn wakeup_with(&self, event: T) -> Result<(), WakeupError<T>> {
+        self.send(event, false)
+    }
+
+    fn as_sender(&self) -> Option<Sender<T>> {
+        self.producers.lock().unwrap().untracked = true;
+        Some(self.sender.clone())
+    }
+}
+
+/// Wakes up a `ChannelEventsLoop` from any thread, coalescing the wakeups that are already
+/// pending. See `ChannelEventsLoop::create_proxy_coalesced`.
+pub struct CoalescedProxy<T> {
+    proxy: ChannelProxy<T>,
+    wakeup_pending: Arc<AtomicBool>,
+}
+
+impl<T> Clone for CoalescedProxy<T> {
+    fn clone(&self) -> CoalescedProxy<T> {
+        CoalescedProxy {
+            proxy: Clone::clone(&self.proxy),
+            wakeup_pending: self.wakeup_pending.clone(),
+        }
+    }
+}
+
+impl<T> EventsLoopProxy for CoalescedProxy<T>
+where
+    T: FromWakeup + Send + 'static,
+{
+    fn wakeup(&self) -> Result<(), EventsLoopClosed> {
+        if self.wakeup_pending.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.proxy.send(T::from_wakeup(), true).map_err(|_| {
+            self.wakeup_pending.store(false, Ordering::SeqCst);
+            EventsLoopClosed
+        })
+    }
+
+    fn clone(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(Clone::clone(self))
+    }
+
+    fn pending_wakeups(&self) -> Option<usize> {
+        self.proxy.pending_wakeups()
+    }
+}
+
+// Wakes up a `ChannelEventsLoop` without counting as one of its producers. It sends through the
+// `Sender` kept by the producers, so its wakeups fail while none of them is live.
+struct WakeupProxy<T> {
+    pending: Arc<AtomicUsize>,
+    producers: Arc<Mutex<Producers<T>>>,
+    plain_wakeups: Arc<AtomicUsize>,
+}
+
+impl<T> EventsLoopProxy for WakeupProxy<T>
+where
+    T: FromWakeup + Send + 'static,
+{
+    fn wakeup(&self) -> Result<(), EventsLoopClosed> {
+        let producers = self.producers.lock().unwrap();
+        let sender = producers.sender.as_ref().ok_or(EventsLoopClosed)?;
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        self.plain_wakeups.fetch_add(1, Ordering::SeqCst);
+        sender.send(T::from_wakeup()).map_err(|_| {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            self.plain_wakeups.fetch_sub(1, Ordering::SeqCst);
+            EventsLoopClosed
+        })
+    }
+
+    fn clone(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(WakeupProxy {
+            pending: self.pending.clone(),
+            producers: self.producers.clone(),
+            plain_wakeups: self.plain_wakeups.clone(),
+        })
+    }
+
+    fn pending_wakeups(&self) -> Option<usize> {
+        Some(self.pending.load(Ordering::Relaxed))
+    }
+}
+
+/// Returns a `Sender` whose events are delivered to the loop of `proxy`.
+///
+/// If the loop is backed by a channel, this is the `Sender` returned by
+/// `EventsLoopPayloadProxy::as_sender`. Otherwise a forwarding thread is spawned, which receives
+/// the events sent to the returned `Sender` and passes them to `proxy.wakeup_with`. This has a few
+/// implications:
+///
+/// - Each call spawns its own thread, which adds a hop of latency to every event. The events sent
+///   through one bridge keep their order.
+/// - If the loop rejects an event because it is full, the thread retries until it is accepted,
+///   so a slow loop applies backpressure to the bridge rather than losing events.
+/// - The thread exits once every clone of the `Sender` has been dropped, or once the loop is
+///   closed. From then on, the events sent to the bridge are silently discarded.
+pub fn bridge<T, P>(proxy: P) -> Sender<T>
+where
+    T: Send + 'static,
+    P: EventsLoopPayloadProxy<T> + 'static,
+{
+    if let Some(sender) = proxy.as_sender() {
+        return sender;
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for mut event in receiver {
+            loop {
+                match proxy.wakeup_with(event) {
+                    Ok(()) => break,
+                    Err(WakeupError::Full(back)) => {
+                        event = back;
+                        thread::yield_now();
+                    }
+                    Err(WakeupError::Closed(_)) => return,
+                }
+            }
+        }
+    });
+    sender
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::{bridge, ChannelEventsLoop};
+    use bounded::BoundedEventsLoop;
+    use mock::Event;
+    use {ControlFlow, EventsLoop, EventsLoopPayloadProxy, EventsLoopProxy, OverflowPolicy};
+
+    #[test]
+    fn pending_events_survive_a_reload() {
+        let mut old = ChannelEventsLoop::new();
+        let proxy = old.create_payload_proxy();
+        for n in 0..5 {
+            proxy.wakeup_with(Event::Data(n)).unwrap();
+        }
+        let mut delivered = Vec::new();
+        old.run(&mut |e| {
+            delivered.push(e);
+            if delivered.len() == 2 {
+                ControlFlow::Break
+            } else {
+                ControlFlow::Continue
+            }
+        });
+
+        let state = old.drain_state();
+        assert_eq!(old.pending_count(), Some(0));
+        drop(old);
+        let mut new = ChannelEventsLoop::with_initial_events(state);
+        assert_eq!(new.pending_count(), Some(3));
+        new.create_payload_proxy()
+            .wakeup_with(Event::Data(5))
+            .unwrap();
+        new.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered, (0..6).map(Event::Data).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn channel_proxy_exposes_its_sender() {
+        let mut l = ChannelEventsLoop::new();
+        let sender = l.create_payload_proxy().as_sender().unwrap();
+        sender.send(Event::Data(1)).unwrap();
+        sender.send(Event::Data(2)).unwrap();
+
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered, vec![Event::Data(1), Event::Data(2)]);
+        // The `Sender` is still around, although the proxy is gone.
+        assert!(!l.is_closed());
+    }
+
+    #[test]
+    fn run_returns_once_the_proxies_are_gone() {
+        let mut l = ChannelEventsLoop::new();
+        let proxy = l.create_payload_proxy();
+        let producer = thread::spawn(move || {
+            for n in 0..3 {
+                proxy.wakeup_with(Event::Data(n)).unwrap();
+            }
+        });
+        let mut delivered = Vec::new();
+        l.run(&mut |e| {
+            delivered.push(e);
+            ControlFlow::Continue
+        });
+        producer.join().unwrap();
+        assert_eq!(delivered, (0..3).map(Event::Data).collect::<Vec<_>>());
+        assert!(l.is_closed());
+
+        // A new proxy reopens the loop.
+        let proxy = l.create_payload_proxy();
+        assert!(!l.is_closed());
+        proxy.wakeup_with(Event::Data(3)).unwrap();
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered.len(), 4);
+    }
+
+    #[test]
+    fn proxy_counts_pending_wakeups() {
+        let mut l = ChannelEventsLoop::<Event>::new();
+        let proxy = l.create_payload_proxy();
+        for _ in 0..4 {
+            proxy.wakeup().unwrap();
+        }
+        proxy.wakeup_with(Event::Data(1)).unwrap();
+        assert_eq!(proxy.pending_wakeups(), Some(5));
+
+        let mut remaining = Vec::new();
+        l.run(&mut |_| {
+            remaining.push(proxy.pending_wakeups().unwrap());
+            if remaining.len() == 2 {
+                ControlFlow::Break
+            } else {
+                ControlFlow::Continue
+            }
+        });
+        assert_eq!(remaining, vec![4, 3]);
+
+        l.poll_events(&mut |_| ());
+        assert_eq!(proxy.pending_wakeups(), Some(0));
+    }
+
+    #[test]
+    fn coalesced_wakeups_collapse_into_one() {
+        let mut l = ChannelEventsLoop::<Event>::new();
+        let producers = (0..4)
+            .map(|_| {
+                let proxy = l.create_proxy_coalesced();
+                thread::spawn(move || {
+                    for _ in 0..250 {
+                        proxy.wakeup().unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        let mut awakened = 0;
+        l.poll_events(&mut |_| awakened += 1);
+        assert_eq!(awakened, 1);
+
+        l.create_proxy_coalesced().wakeup().unwrap();
+        l.poll_events(&mut |_| awakened += 1);
+        assert_eq!(awakened, 2);
+    }
+
+    #[test]
+    fn plain_wakeups_leave_the_coalesced_one_pending() {
+        let mut l = ChannelEventsLoop::<Event>::new();
+        let plain = l.create_payload_proxy();
+        let coalesced = l.create_proxy_coalesced();
+        plain.wakeup().unwrap();
+        coalesced.wakeup().unwrap();
+
+        // Receives the plain wakeup only, so the coalesced one is still queued.
+        l.run(&mut |_| ControlFlow::Break);
+        coalesced.wakeup().unwrap();
+
+        let mut awakened = 0;
+        l.poll_events(&mut |_| awakened += 1);
+        assert_eq!(awakened, 1);
+    }
+
+    #[test]
+    fn on_closed_runs_once_the_loop_is_dropped() {
+        let l = ChannelEventsLoop::<Event>::new();
+        let proxy = l.create_payload_proxy();
+        let (sender, receiver) = mpsc::channel();
+        let first = sender.clone();
+        proxy.on_closed(move || first.send("first").unwrap());
+        let second = sender.clone();
+        proxy.on_closed(move || second.send("second").unwrap());
+        assert!(receiver.try_recv().is_err());
+
+        drop(l);
+        assert!(proxy.wakeup().is_err());
+        proxy.on_closed(move || sender.send("late").unwrap());
+        assert_eq!(
+            receiver.iter().collect::<Vec<_>>(),
+            vec!["first", "second", "late"]
+        );
+    }
+
+    #[test]
+    fn bridge_forwards_to_loops_without_a_channel() {
+        let mut l = BoundedEventsLoop::with_capacity(1, OverflowPolicy::Reject);
+        let proxy = l.create_payload_proxy();
+        assert!(proxy.as_sender().is_none());
+
+        let sender = bridge(proxy);
+        for n in 0..5 {
+            sender.send(Event::Data(n)).unwrap();
+        }
+
+        let mut delivered = Vec::new();
+        l.run(&mut |e| {
+            delivered.push(e);
+            if delivered.len() == 5 {
+                ControlFlow::Break
+            } else {
+                ControlFlow::Continue
+            }
+        });
+        assert_eq!(delivered, (0..5).map(Event::Data).collect::<Vec<_>>());
+    }
+}
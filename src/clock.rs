@@ -0,0 +1,155 @@
+//! Injectable source of time, so that time-based loops and adapters can be tested without
+//! actually sleeping.
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A source of time.
+pub trait Clock {
+    /// Returns the current instant.
+    fn now(&self) -> Instant;
+
+    /// Blocks the current thread until `deadline` is reached. Returns immediately if the deadline
+    /// is already in the past.
+    fn sleep_until(&self, deadline: Instant);
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        (**self).sleep_until(deadline)
+    }
+}
+
+/// The real clock, based on `Instant::now` and `thread::sleep`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        let now = Instant::now();
+        if deadline > now {
+            thread::sleep(deadline - now);
+        }
+    }
+}
+
+/// A virtual clock that only moves when told to.
+///
+/// Clones share the same time, so a test can keep one clone to advance the time while a loop or
+/// an adapter holds another. Sleeping on a `MockClock` doesn't block: it moves the time forward
+/// to the deadline instead.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    now: Rc<Cell<Instant>>,
+}
+
+impl MockClock {
+    /// Builds a clock whose time starts at the current real instant.
+    pub fn new() -> MockClock {
+        MockClock {
+            now: Rc::new(Cell::new(Instant::now())),
+        }
+    }
+
+    /// Moves the time forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+
+    /// Moves the time forward to `instant`. Does nothing if `instant` is in the past, as the
+    /// clock never goes backwards.
+    pub fn advance_to(&self, instant: Instant) {
+        if instant > self.now.get() {
+            self.now.set(instant);
+        }
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> MockClock {
+        MockClock::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        self.advance_to(deadline);
+    }
+}
+
+// The sleep of a loop that polls others while they are all idle. It doubles after every idle
+// round, from `min` up to `max`, and starts over from `min` once an event is delivered.
+pub(crate) struct IdleBackoff {
+    pub min: Duration,
+    pub max: Duration,
+    next: Duration,
+}
+
+impl IdleBackoff {
+    pub fn new(min: Duration, max: Duration) -> IdleBackoff {
+        IdleBackoff {
+            min,
+            max,
+            next: min,
+        }
+    }
+
+    // Returns how long to sleep after an idle round, and doubles it for the next one.
+    pub fn next(&mut self) -> Duration {
+        let sleep = self.next.max(self.min).min(self.max);
+        self.next = sleep * 2;
+        sleep
+    }
+
+    pub fn reset(&mut self) {
+        self.next = self.min;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Clock, IdleBackoff, MockClock};
+
+    #[test]
+    fn idle_backoff_doubles_up_to_the_cap() {
+        let ms = Duration::from_millis;
+        let mut backoff = IdleBackoff::new(ms(1), ms(5));
+        let sleeps: Vec<_> = (0..5).map(|_| backoff.next()).collect();
+        assert_eq!(sleeps, vec![ms(1), ms(2), ms(4), ms(5), ms(5)]);
+
+        backoff.reset();
+        assert_eq!(backoff.next(), ms(1));
+    }
+
+    #[test]
+    fn mock_clock_is_shared_and_monotonic() {
+        let clock = MockClock::new();
+        let other = clock.clone();
+        let start = clock.now();
+
+        other.advance(Duration::from_millis(10));
+        assert_eq!(clock.now(), start + Duration::from_millis(10));
+
+        clock.sleep_until(start);
+        assert_eq!(other.now(), start + Duration::from_millis(10));
+
+        clock.sleep_until(start + Duration::from_millis(25));
+        assert_eq!(other.now(), start + Duration::from_millis(25));
+    }
+}
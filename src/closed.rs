@@ -0,0 +1,37 @@
+use std::sync::Mutex;
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+/// The callbacks to run once a loop is closed, shared between the loop and its proxies.
+pub(crate) struct ClosedCallbacks {
+    // `None` once the loop is closed.
+    callbacks: Mutex<Option<Vec<Callback>>>,
+}
+
+impl ClosedCallbacks {
+    pub fn new() -> ClosedCallbacks {
+        ClosedCallbacks {
+            callbacks: Mutex::new(Some(Vec::new())),
+        }
+    }
+
+    /// Registers `f` to run once the loop is closed, or runs it right away if it already is.
+    pub fn register(&self, f: Callback) {
+        let mut callbacks = self.callbacks.lock().unwrap();
+        match *callbacks {
+            Some(ref mut callbacks) => callbacks.push(f),
+            None => {
+                drop(callbacks);
+                f();
+            }
+        }
+    }
+
+    /// Marks the loop as closed and runs the registered callbacks, in registration order.
+    pub fn close(&self) {
+        let callbacks = self.callbacks.lock().unwrap().take();
+        for f in callbacks.into_iter().flatten() {
+            f();
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Collapsing bursts of events down to the latest one per slot.
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+use {ControlFlow, DropSink, EventsLoop, EventsLoopProxy};
+
+type SlotFn<'a, Event, K> = Box<dyn FnMut(&Event) -> Option<K> + 'a>;
+
+/// Wraps an `EventsLoop` and, within each drain, keeps only the last event of every slot.
+///
+/// The slot of an event is computed by the key function given to `new`, for example the id of
+/// the window a resize event is about, so that a burst of resizes collapses into one up-to-date
+/// event per window. The events for which the key function returns `None` aren't coalesced. Every
+/// slot is delivered once, at the position where it was first seen in the drain, carrying its
+/// latest event. The events that were superseded are reported to the `DropSink` given to
+/// `with_drop_sink`, if any.
+///
+/// To know which event is the latest, the adapter buffers a full drain of the inner loop before
+/// delivering anything: `poll_events` drains everything that is pending, and `run` waits for an
+/// event and then drains the ones pending behind it. Events never get coalesced across drains.
+/// If the callback of `run` exits, the rest of the drain is kept for the next call.
+pub struct CoalesceLatest<'a, L, Event, K> {
+    inner: L,
+    key: SlotFn<'a, Event, K>,
+    drop_sink: Option<DropSink<Event>>,
+    ready: VecDeque<Event>,
+}
+
+impl<'a, L, Event, K> CoalesceLatest<'a, L, Event, K>
+where
+    L: EventsLoop<Event>,
+    K: Eq + Hash,
+{
+    /// Builds the adapter around `inner`, computing the slot of each event with `key`.
+    pub fn new<F>(inner: L, key: F) -> CoalesceLatest<'a, L, Event, K>
+    where
+        F: FnMut(&Event) -> Option<K> + 'a,
+    {
+        CoalesceLatest {
+            inner,
+            key: Box::new(key),
+            drop_sink: None,
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Reports the superseded events to `sink`.
+    pub fn with_drop_sink(mut self, sink: DropSink<Event>) -> CoalesceLatest<'a, L, Event, K> {
+        self.drop_sink = Some(sink);
+        self
+    }
+
+    /// Returns the number of coalesced events waiting to be delivered.
+    pub fn buffered(&self) -> usize {
+        self.ready.len()
+    }
+
+    /// Returns the wrapped loop. The buffered events are dropped.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+// Drains `inner`, starting with `first` if any, and appends the latest event of every slot to
+// `ready`, in first-seen order.
+fn coalesce<L, Event, K>(
+    inner: &mut L,
+    key: &mut dyn FnMut(&Event) -> Option<K>,
+    drop_sink: &Option<DropSink<Event>>,
+    ready: &mut VecDeque<Event>,
+    first: Option<Event>,
+) where
+    L: EventsLoop<Event>,
+    K: Eq + Hash,
+{
+    let mut drain = Vec::new();
+    let mut slots = HashMap::new();
+    let mut keep = |event: Event| {
+        let slot = match key(&event) {
+            Some(slot) => slot,
+            None => return drain.push(Some(event)),
+        };
+        match slots.get(&slot) {
+            Some(&index) => {
+                let latest: &mut Option<Event> = &mut drain[index];
+                if let (Some(superseded), Some(ref sink)) = (latest.replace(event), drop_sink) {
+                    (*sink.borrow_mut())(superseded);
+                }
+            }
+            None => {
+                slots.insert(slot, drain.len());
+                drain.push(Some(event));
+            }
+        }
+    };
+    if let Some(event) = first {
+        keep(event);
+    }
+    inner.poll_events(&mut keep);
+    ready.extend(drain.into_iter().flatten());
+}
+
+impl<'a, L, Event, K> EventsLoop<Event> for CoalesceLatest<'a, L, Event, K>
+where
+    L: EventsLoop<Event>,
+    K: Eq + Hash,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let CoalesceLatest {
+            ref mut inner,
+            ref mut key,
+            ref drop_sink,
+            ref mut ready,
+        } = *self;
+        coalesce(inner, &mut **key, drop_sink, ready, None);
+        while let Some(event) = ready.pop_front() {
+            callback(event);
+        }
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let CoalesceLatest {
+            ref mut inner,
+            ref mut key,
+            ref drop_sink,
+            ref mut ready,
+        } = *self;
+        loop {
+            while let Some(event) = ready.pop_front() {
+                if callback(event).is_exit() {
+                    return;
+                }
+            }
+
+            let mut first = None;
+            inner.run(&mut |event| {
+                first = Some(event);
+                ControlFlow::Break
+            });
+            if first.is_none() {
+                return;
+            }
+            coalesce(inner, &mut **key, drop_sink, ready, first);
+        }
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    /// Returns `true` once the inner loop is closed and no event is buffered.
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed() && self.ready.is_empty()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    /// Counts the buffered events along with those pending in the inner loop.
+    fn pending_count(&self) -> Option<usize> {
+        self.inner
+            .pending_count()
+            .map(|pending| pending + self.buffered())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::CoalesceLatest;
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop};
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Event {
+        Resized(u32, u32),
+        Key(char),
+    }
+
+    fn window(event: &Event) -> Option<u32> {
+        match *event {
+            Event::Resized(window, _) => Some(window),
+            Event::Key(_) => None,
+        }
+    }
+
+    #[test]
+    fn keeps_the_latest_event_per_slot() {
+        let burst = vec![
+            Event::Resized(1, 10),
+            Event::Resized(2, 20),
+            Event::Key('a'),
+            Event::Resized(1, 11),
+            Event::Resized(2, 21),
+            Event::Resized(1, 12),
+            Event::Key('b'),
+            Event::Resized(3, 30),
+        ];
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let sink = dropped.clone();
+        let mut l = CoalesceLatest::new(MockEventsLoop::new(burst), window)
+            .with_drop_sink(Rc::new(RefCell::new(move |e| sink.borrow_mut().push(e))));
+
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(
+            delivered,
+            vec![
+                Event::Resized(1, 12),
+                Event::Resized(2, 21),
+                Event::Key('a'),
+                Event::Key('b'),
+                Event::Resized(3, 30),
+            ]
+        );
+        assert_eq!(
+            *dropped.borrow(),
+            vec![
+                Event::Resized(1, 10),
+                Event::Resized(2, 20),
+                Event::Resized(1, 11)
+            ]
+        );
+    }
+
+    #[test]
+    fn buffered_events_are_pending() {
+        let burst = vec![
+            Event::Resized(1, 10),
+            Event::Key('a'),
+            Event::Resized(2, 20),
+        ];
+        let mut l = CoalesceLatest::new(MockEventsLoop::new(burst), window);
+        l.run(&mut |_| ControlFlow::Break);
+        assert_eq!(l.buffered(), 2);
+        assert_eq!(l.pending_count(), Some(2));
+    }
+
+    #[test]
+    fn drains_are_coalesced_separately() {
+        let mut l = CoalesceLatest::new(MockEventsLoop::new(vec![Event::Resized(1, 10)]), window);
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |e| delivered.push(e));
+
+        l.inner.push(Event::Resized(1, 11));
+        l.inner.push(Event::Resized(1, 12));
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(
+            delivered,
+            vec![Event::Resized(1, 10), Event::Resized(1, 12)]
+        );
+    }
+}
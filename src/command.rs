@@ -0,0 +1,302 @@
+//! Sending control commands to a loop, apart from its events.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+
+use {ControlFlow, EventsLoop, EventsLoopProxy, FromWakeup, WakeupError};
+
+/// Wraps an `EventsLoop` and gives it a second, typed channel for control commands, such as
+/// pausing or changing the configuration, that are handled apart from the events.
+///
+/// The commands are sent from any thread through a `CommandSender`, and dispatched to the
+/// handler given to `new` on the thread of the loop. They take priority over the events: `run`
+/// takes the events in batches from the inner loop, and dispatches every pending command before
+/// delivering each batch. A command sent before an event was taken from the inner loop is thus
+/// always handled before that event is delivered. If the handler returns `ControlFlow::Break` or
+/// `ControlFlow::ExitWithCode`, `run` returns right away, and the events of the batch are kept
+/// for the next call.
+///
+/// `poll_events` takes a batch and dispatches the commands the same way, holding the batch back
+/// if the handler exits.
+///
+/// Sending a command wakes the inner loop up, so that a blocked `run` gets to dispatch it. The
+/// adapter counts these wakeups and swallows as many wakeup events, recognized with
+/// `FromWakeup::is_wakeup`, so that they don't reach the callback. A backend that coalesces
+/// wakeups can deliver fewer of them than were sent, in which case a later wakeup of the
+/// application is swallowed in their place.
+pub struct CommandLoop<'a, L, Event, Cmd> {
+    inner: L,
+    sender: Sender<Cmd>,
+    commands: Receiver<Cmd>,
+    wakeups: Arc<AtomicUsize>,
+    handler: Box<dyn FnMut(Cmd) -> ControlFlow + 'a>,
+    batch: VecDeque<Event>,
+}
+
+impl<'a, L, Event, Cmd> CommandLoop<'a, L, Event, Cmd>
+where
+    L: EventsLoop<Event>,
+{
+    /// Builds the adapter around `inner`, dispatching the commands to `handler`.
+    pub fn new<F>(inner: L, handler: F) -> CommandLoop<'a, L, Event, Cmd>
+    where
+        F: FnMut(Cmd) -> ControlFlow + 'a,
+    {
+        let (sender, commands) = mpsc::channel();
+        CommandLoop {
+            inner,
+            sender,
+            commands,
+            wakeups: Arc::new(AtomicUsize::new(0)),
+            handler: Box::new(handler),
+            batch: VecDeque::new(),
+        }
+    }
+
+    /// Creates a sender that can send commands to the loop from another thread.
+    pub fn sender(&self) -> CommandSender<Cmd> {
+        CommandSender {
+            sender: self.sender.clone(),
+            proxy: self.inner.create_proxy(),
+            wakeups: self.wakeups.clone(),
+        }
+    }
+
+    /// Returns the wrapped loop. The pending commands and the events held back are dropped.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+// Hands every pending command to `handler`, and returns the flow of the last one, or of the
+// first one that exits.
+fn dispatch<Cmd>(
+    commands: &Receiver<Cmd>,
+    handler: &mut dyn FnMut(Cmd) -> ControlFlow,
+) -> ControlFlow {
+    let mut flow = ControlFlow::Continue;
+    while let Ok(command) = commands.try_recv() {
+        flow = handler(command);
+        if flow.is_exit() {
+            break;
+        }
+    }
+    flow
+}
+
+// Returns `true` if `event` is the wakeup of a command, which is then no longer expected.
+fn is_command_wakeup<Event: FromWakeup>(wakeups: &AtomicUsize, event: &Event) -> bool {
+    event.is_wakeup()
+        && wakeups
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+}
+
+impl<'a, L, Event, Cmd> EventsLoop<Event> for CommandLoop<'a, L, Event, Cmd>
+where
+    L: EventsLoop<Event>,
+    Event: FromWakeup,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let CommandLoop {
+            ref mut inner,
+            ref commands,
+            ref wakeups,
+            ref mut handler,
+            ref mut batch,
+            ..
+        } = *self;
+        inner.poll_events(&mut |event| {
+            if !is_command_wakeup(wakeups, &event) {
+                batch.push_back(event);
+            }
+        });
+        if dispatch(commands, &mut **handler).is_exit() {
+            return;
+        }
+        while let Some(event) = batch.pop_front() {
+            callback(event);
+        }
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let CommandLoop {
+            ref mut inner,
+            ref commands,
+            ref wakeups,
+            ref mut handler,
+            ref mut batch,
+            ..
+        } = *self;
+        loop {
+            if batch.is_empty() {
+                inner.poll_events(&mut |event| {
+                    if !is_command_wakeup(wakeups, &event) {
+                        batch.push_back(event);
+                    }
+                });
+            }
+            if dispatch(commands, &mut **handler).is_exit() {
+                return;
+            }
+            if batch.is_empty() {
+                let mut woken = false;
+                inner.run(&mut |event| {
+                    if is_command_wakeup(wakeups, &event) {
+                        woken = true;
+                    } else {
+                        batch.push_back(event);
+                    }
+                    ControlFlow::Break
+                });
+                if batch.is_empty() && !woken {
+                    return;
+                }
+                // Dispatch the commands that came with the wakeup first.
+                continue;
+            }
+            while let Some(event) = batch.pop_front() {
+                if callback(event).is_exit() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    /// Returns `true` once the inner loop is closed and no event is held back.
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed() && self.batch.is_empty()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    /// Counts the events held back along with those pending in the inner loop.
+    fn pending_count(&self) -> Option<usize> {
+        self.inner
+            .pending_count()
+            .map(|pending| pending + self.batch.len())
+    }
+}
+
+/// Sends commands to a `CommandLoop` from any thread.
+pub struct CommandSender<Cmd> {
+    sender: Sender<Cmd>,
+    proxy: Box<dyn EventsLoopProxy>,
+    wakeups: Arc<AtomicUsize>,
+}
+
+impl<Cmd> Clone for CommandSender<Cmd> {
+    fn clone(&self) -> CommandSender<Cmd> {
+        CommandSender {
+            sender: self.sender.clone(),
+            proxy: EventsLoopProxy::clone(&*self.proxy),
+            wakeups: self.wakeups.clone(),
+        }
+    }
+}
+
+impl<Cmd> CommandSender<Cmd> {
+    /// Sends `command` to the loop and wakes it up.
+    ///
+    /// Returns `WakeupError::Closed` if the loop no longer exists.
+    pub fn send(&self, command: Cmd) -> Result<(), WakeupError<Cmd>> {
+        self.sender
+            .send(command)
+            .map_err(|error| WakeupError::Closed(error.0))?;
+        // Counted before the wakeup, so that the loop can't receive it unexpected. The command is
+        // queued either way, and dispatched as soon as the loop runs.
+        self.wakeups.fetch_add(1, Ordering::SeqCst);
+        if self.proxy.wakeup().is_err() {
+            self.wakeups.fetch_sub(1, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::CommandLoop;
+    use bounded::BoundedEventsLoop;
+    use mock::Event;
+    use {ControlFlow, EventsLoop, EventsLoopProxy, OverflowPolicy};
+
+    #[derive(Debug, PartialEq)]
+    enum Cmd {
+        Pause,
+        Resume,
+    }
+
+    #[test]
+    fn commands_are_handled_before_queued_events() {
+        let inner = BoundedEventsLoop::with_capacity(8, OverflowPolicy::Reject);
+        let proxy = inner.create_payload_proxy();
+        let handled = RefCell::new(Vec::new());
+        let mut l = CommandLoop::new(inner, |command| {
+            let flow = match command {
+                Cmd::Pause => ControlFlow::Break,
+                Cmd::Resume => ControlFlow::Continue,
+            };
+            handled.borrow_mut().push(command);
+            flow
+        });
+        let commands = l.sender();
+
+        proxy.wakeup_with(Event::Data(1)).unwrap();
+        proxy.wakeup_with(Event::Data(2)).unwrap();
+        commands.send(Cmd::Pause).unwrap();
+
+        let mut delivered = Vec::new();
+        let mut callback = |event| match event {
+            Event::Data(n) => {
+                delivered.push(n);
+                if n == 3 {
+                    ControlFlow::Break
+                } else {
+                    ControlFlow::Continue
+                }
+            }
+            Event::Awakened => panic!("the wakeup of a command was delivered"),
+        };
+        l.run(&mut callback);
+        assert_eq!(*handled.borrow(), vec![Cmd::Pause]);
+        assert_eq!(l.pending_count(), Some(2));
+
+        commands.send(Cmd::Resume).unwrap();
+        proxy.wakeup_with(Event::Data(3)).unwrap();
+        l.run(&mut callback);
+        assert_eq!(*handled.borrow(), vec![Cmd::Pause, Cmd::Resume]);
+        assert_eq!(delivered, vec![1, 2, 3]);
+        assert_eq!(l.pending_count(), Some(0));
+    }
+
+    #[test]
+    fn other_wakeups_are_delivered() {
+        let inner = BoundedEventsLoop::<Event>::with_capacity(8, OverflowPolicy::Reject);
+        let proxy = inner.create_payload_proxy();
+        let mut l = CommandLoop::new(inner, |_: Cmd| ControlFlow::Continue);
+        l.sender().send(Cmd::Resume).unwrap();
+        proxy.wakeup().unwrap();
+
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered, vec![Event::Awakened]);
+    }
+}
@@ -0,0 +1,288 @@
+//! Running several loops of different types cooperatively on one thread.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use clock::{Clock, IdleBackoff, SystemClock};
+use {ControlFlow, EventsLoop};
+
+/// How long `CoopScheduler::run` and the merges of `merge` first sleep once every loop is idle,
+/// unless configured otherwise.
+pub const DEFAULT_IDLE_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The longest that `CoopScheduler::run` and the merges of `merge` sleep between rounds while
+/// every loop stays idle, unless configured otherwise.
+pub const DEFAULT_MAX_IDLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How many events of each loop `CoopScheduler` delivers per round, unless configured otherwise.
+pub const DEFAULT_BUDGET: usize = 64;
+
+// A loop along with its callback, with the event type erased.
+trait Task {
+    // Delivers up to `budget` events, taking them from the loop if none are buffered. Returns
+    // the number of events delivered, and the flow of the last callback.
+    fn round(&mut self, budget: usize) -> (usize, ControlFlow);
+
+    // Returns the deadline requested by the callback, forgetting it once it is past `now`.
+    fn deadline(&mut self, now: Instant) -> Option<Instant>;
+
+    // Blocks until the loop has an event. Returns `false` if its `run` returned without one.
+    fn wait(&mut self) -> bool;
+
+    fn is_done(&self) -> bool;
+}
+
+struct LoopTask<'a, L, Event> {
+    events_loop: L,
+    callback: Box<dyn FnMut(Event) -> ControlFlow + 'a>,
+    buffer: VecDeque<Event>,
+    deadline: Option<Instant>,
+}
+
+impl<'a, L, Event> Task for LoopTask<'a, L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    fn round(&mut self, budget: usize) -> (usize, ControlFlow) {
+        let LoopTask {
+            ref mut events_loop,
+            ref mut callback,
+            ref mut buffer,
+            ref mut deadline,
+        } = *self;
+        if buffer.is_empty() {
+            events_loop.poll_events(&mut |event| buffer.push_back(event));
+        }
+        let mut delivered = 0;
+        let mut flow = ControlFlow::Continue;
+        while delivered < budget {
+            let event = match buffer.pop_front() {
+                Some(event) => event,
+                None => break,
+            };
+            delivered += 1;
+            flow = callback(event);
+            match flow {
+                ControlFlow::Continue => {}
+                ControlFlow::WaitUntil(at) => *deadline = Some(at),
+                ControlFlow::Break | ControlFlow::ExitWithCode(_) => break,
+            }
+        }
+        (delivered, flow)
+    }
+
+    fn deadline(&mut self, now: Instant) -> Option<Instant> {
+        if self.deadline.is_some_and(|at| at <= now) {
+            self.deadline = None;
+        }
+        self.deadline
+    }
+
+    fn wait(&mut self) -> bool {
+        let buffer = &mut self.buffer;
+        self.events_loop.run(&mut |event| {
+            buffer.push_back(event);
+            ControlFlow::Break
+        });
+        !buffer.is_empty()
+    }
+
+    fn is_done(&self) -> bool {
+        self.buffer.is_empty() && self.events_loop.is_closed()
+    }
+}
+
+/// Runs several loops cooperatively on the current thread, each with its own event type and
+/// callback.
+///
+/// This is meant for loops that can't be moved to threads of their own, such as a network loop
+/// and a UI loop that are both `!Send`. Unlike `merge_all`, which merges loops of the same
+/// type into one stream, every loop keeps its callback. Each round of `run` serves every loop in
+/// turn, in an order whose starting point rotates from one round to the next, and delivers at
+/// most the budget of events of each, buffering the rest, so that a busy loop can't starve the
+/// others. The loops that report themselves closed through `EventsLoop::is_closed` are removed
+/// once their buffered events are delivered.
+///
+/// `run` only sleeps when every loop is idle. A loop can't wait on several others at once, so
+/// while more than one loop is left this is polling, not blocking: `run` sleeps between rounds,
+/// or until the earliest `ControlFlow::WaitUntil` deadline returned by the callbacks if that
+/// comes first. The sleep starts at the idle interval and doubles with every idle round, up to
+/// the maximum idle interval, which bounds both the latency of the first event after a quiet
+/// spell and the number of wakeups of an idle scheduler. It starts over once an event is
+/// delivered. `run` only blocks on `EventsLoop::run` once a single loop is left. It returns as
+/// soon as any callback breaks, or once every loop has been removed.
+pub struct CoopScheduler<'a> {
+    tasks: Vec<Box<dyn Task + 'a>>,
+    next: usize,
+    budget: usize,
+    idle: IdleBackoff,
+}
+
+impl<'a> Default for CoopScheduler<'a> {
+    fn default() -> CoopScheduler<'a> {
+        CoopScheduler::new()
+    }
+}
+
+impl<'a> CoopScheduler<'a> {
+    /// Builds a scheduler without any loop.
+    pub fn new() -> CoopScheduler<'a> {
+        CoopScheduler {
+            tasks: Vec::new(),
+            next: 0,
+            budget: DEFAULT_BUDGET,
+            idle: IdleBackoff::new(DEFAULT_IDLE_INTERVAL, DEFAULT_MAX_IDLE_INTERVAL),
+        }
+    }
+
+    /// Sets how many events of each loop are delivered per round. A budget of zero counts as one.
+    pub fn with_budget(mut self, budget: usize) -> CoopScheduler<'a> {
+        self.budget = budget.max(1);
+        self
+    }
+
+    /// Sets how long `run` first sleeps once every loop is idle.
+    pub fn with_idle_interval(mut self, interval: Duration) -> CoopScheduler<'a> {
+        self.idle = IdleBackoff::new(interval, self.idle.max);
+        self
+    }
+
+    /// Sets the longest that `run` sleeps between rounds while every loop stays idle.
+    pub fn with_max_idle_interval(mut self, interval: Duration) -> CoopScheduler<'a> {
+        self.idle = IdleBackoff::new(self.idle.min, interval);
+        self
+    }
+
+    /// Adds `events_loop` to the scheduler, delivering its events to `callback`.
+    pub fn add<L, Event, F>(&mut self, events_loop: L, callback: F)
+    where
+        L: EventsLoop<Event> + 'a,
+        Event: 'a,
+        F: FnMut(Event) -> ControlFlow + 'a,
+    {
+        self.tasks.push(Box::new(LoopTask {
+            events_loop,
+            callback: Box::new(callback),
+            buffer: VecDeque::new(),
+            deadline: None,
+        }));
+    }
+
+    /// Returns the number of loops that are still scheduled.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Returns `true` if every loop has been removed.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Runs one round, like `run` does, without sleeping. Returns the number of events that
+    /// were delivered, and the flow of the first callback that broke, if any.
+    pub fn poll_events(&mut self) -> (usize, ControlFlow) {
+        let budget = self.budget;
+        let count = self.tasks.len();
+        let mut delivered = 0;
+        let mut flow = ControlFlow::Continue;
+        for i in 0..count {
+            let (n, last) = self.tasks[(self.next + i) % count].round(budget);
+            delivered += n;
+            if last.is_exit() {
+                flow = last;
+                break;
+            }
+        }
+        if count > 0 {
+            self.next = (self.next + 1) % count;
+        }
+
+        self.tasks.retain(|task| !task.is_done());
+        if self.next >= self.tasks.len() {
+            self.next = 0;
+        }
+        (delivered, flow)
+    }
+
+    /// Runs the loops until a callback breaks or every loop has been removed.
+    pub fn run(&mut self) {
+        loop {
+            let (delivered, flow) = self.poll_events();
+            if flow.is_exit() {
+                return;
+            }
+            if delivered > 0 {
+                self.idle.reset();
+                continue;
+            }
+
+            let now = SystemClock.now();
+            let deadline = self
+                .tasks
+                .iter_mut()
+                .filter_map(|task| task.deadline(now))
+                .min();
+            match (self.tasks.len(), deadline) {
+                (0, _) => return,
+                (1, None) => {
+                    if !self.tasks[0].wait() {
+                        return;
+                    }
+                }
+                (_, deadline) => {
+                    let idle_until = now + self.idle.next();
+                    SystemClock.sleep_until(deadline.map_or(idle_until, |at| at.min(idle_until)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::CoopScheduler;
+    use mock::MockEventsLoop;
+    use ControlFlow;
+
+    #[test]
+    fn loops_take_turns() {
+        let log = RefCell::new(Vec::new());
+        {
+            let mut scheduler = CoopScheduler::new().with_budget(2);
+            scheduler.add(MockEventsLoop::new(vec![1, 2, 3, 4, 5, 6]), |n: u32| {
+                log.borrow_mut().push(format!("net {}", n));
+                ControlFlow::Continue
+            });
+            scheduler.add(MockEventsLoop::new(vec!['a', 'b', 'c']), |c: char| {
+                log.borrow_mut().push(format!("ui {}", c));
+                ControlFlow::Continue
+            });
+            scheduler.run();
+            assert!(scheduler.is_empty());
+        }
+        assert_eq!(
+            log.into_inner(),
+            vec!["net 1", "net 2", "ui a", "ui b", "ui c", "net 3", "net 4", "net 5", "net 6",]
+        );
+    }
+
+    #[test]
+    fn a_breaking_callback_stops_the_scheduler() {
+        let mut scheduler = CoopScheduler::new();
+        scheduler.add(MockEventsLoop::new(vec![1, 2]), |n: u32| {
+            if n == 2 {
+                ControlFlow::Break
+            } else {
+                ControlFlow::Continue
+            }
+        });
+        scheduler.add(MockEventsLoop::new(vec!['a']), |_: char| {
+            ControlFlow::Continue
+        });
+        scheduler.run();
+        // The first loop is done, and the second one never got its turn.
+        assert_eq!(scheduler.len(), 1);
+    }
+}
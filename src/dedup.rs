@@ -0,0 +1,244 @@
+//! Approximate deduplication of high-cardinality event streams.
+
+use std::collections::hash_map::DefaultHasher;
+use std::f64::consts::LN_2;
+use std::hash::{Hash, Hasher};
+
+use {ControlFlow, DropSink, EventsLoop, EventsLoopProxy};
+
+// A fixed-size set of bits answering "probably seen" or "definitely not seen".
+struct BloomFilter {
+    bits: Vec<u64>,
+    len: u64,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    // Sizes the filter for `expected` keys at the given false positive rate, using the usual
+    // m = -n ln(p) / ln(2)^2 bits and k = m / n ln(2) hash functions.
+    fn new(expected: usize, false_positive_rate: f64) -> BloomFilter {
+        let expected = expected.max(1) as f64;
+        let rate = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let len = (-expected * rate.ln() / (LN_2 * LN_2)).ceil().max(64.0) as u64;
+        let hashes = ((len as f64 / expected) * LN_2).round().max(1.0) as u32;
+        BloomFilter {
+            bits: vec![0; len.div_ceil(64) as usize],
+            len,
+            hashes,
+        }
+    }
+
+    // Records `key`, returning `true` if it was probably recorded before.
+    fn insert<K: Hash + ?Sized>(&mut self, key: &K) -> bool {
+        let (h1, h2) = (hash(key, 0), hash(key, 1) | 1);
+        let mut seen = true;
+        for i in 0..u64::from(self.hashes) {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % self.len;
+            let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+            seen &= self.bits[word] & mask != 0;
+            self.bits[word] |= mask;
+        }
+        seen
+    }
+
+    fn clear(&mut self) {
+        for word in &mut self.bits {
+            *word = 0;
+        }
+    }
+}
+
+fn hash<K: Hash + ?Sized>(key: &K, seed: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Wraps an `EventsLoop` and suppresses the events whose key has probably been seen before,
+/// using a Bloom filter.
+///
+/// The filter uses a fixed amount of memory, sized from the expected number of distinct keys and
+/// the acceptable false positive rate. It never lets a duplicate through, but it mistakes some
+/// new keys for seen ones, so a fraction of the genuine events is dropped too. That fraction
+/// stays close to the configured rate as long as the number of distinct keys stays below the
+/// expected one, and grows quickly beyond, at which point the filter should be `reset`. This
+/// suits telemetry pipelines, where losing a few events is an acceptable price for deduplicating
+/// millions of keys; use an exact set otherwise.
+///
+/// Suppressed events are reported to the `DropSink` given to `with_drop_sink`, if any.
+pub struct BloomDedup<'a, L, Event, K> {
+    inner: L,
+    key: Box<dyn FnMut(&Event) -> K + 'a>,
+    filter: BloomFilter,
+    suppressed: usize,
+    drop_sink: Option<DropSink<Event>>,
+}
+
+impl<'a, L, Event, K> BloomDedup<'a, L, Event, K>
+where
+    L: EventsLoop<Event>,
+    K: Hash,
+{
+    /// Builds the adapter around `inner`, sized for `expected` distinct keys at the given false
+    /// positive rate, between `0` and `1`.
+    pub fn new<F>(
+        inner: L,
+        key: F,
+        expected: usize,
+        false_positive_rate: f64,
+    ) -> BloomDedup<'a, L, Event, K>
+    where
+        F: FnMut(&Event) -> K + 'a,
+    {
+        BloomDedup {
+            inner,
+            key: Box::new(key),
+            filter: BloomFilter::new(expected, false_positive_rate),
+            suppressed: 0,
+            drop_sink: None,
+        }
+    }
+
+    /// Reports the suppressed events to `sink`.
+    pub fn with_drop_sink(mut self, sink: DropSink<Event>) -> BloomDedup<'a, L, Event, K> {
+        self.drop_sink = Some(sink);
+        self
+    }
+
+    /// Forgets every key seen so far.
+    pub fn reset(&mut self) {
+        self.filter.clear();
+    }
+
+    /// Returns the number of events suppressed so far.
+    pub fn suppressed(&self) -> usize {
+        self.suppressed
+    }
+
+    /// Returns the wrapped loop.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+// Returns the event if its key is new, and reports it as suppressed otherwise.
+fn admit<'a, Event, K: Hash>(
+    key: &mut Box<dyn FnMut(&Event) -> K + 'a>,
+    filter: &mut BloomFilter,
+    suppressed: &mut usize,
+    drop_sink: &Option<DropSink<Event>>,
+    event: Event,
+) -> Option<Event> {
+    if !filter.insert(&key(&event)) {
+        return Some(event);
+    }
+    *suppressed += 1;
+    if let Some(ref sink) = *drop_sink {
+        (*sink.borrow_mut())(event);
+    }
+    None
+}
+
+impl<'a, L, Event, K> EventsLoop<Event> for BloomDedup<'a, L, Event, K>
+where
+    L: EventsLoop<Event>,
+    K: Hash,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let BloomDedup {
+            ref mut inner,
+            ref mut key,
+            ref mut filter,
+            ref mut suppressed,
+            ref drop_sink,
+        } = *self;
+        inner.poll_events(&mut |event| {
+            if let Some(event) = admit(key, filter, suppressed, drop_sink, event) {
+                callback(event);
+            }
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let BloomDedup {
+            ref mut inner,
+            ref mut key,
+            ref mut filter,
+            ref mut suppressed,
+            ref drop_sink,
+        } = *self;
+        inner.run(
+            &mut |event| match admit(key, filter, suppressed, drop_sink, event) {
+                Some(event) => callback(event),
+                None => ControlFlow::Continue,
+            },
+        );
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    /// Returns the count of the inner loop, which includes the duplicates that won't be
+    /// delivered.
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BloomDedup;
+    use mock::MockEventsLoop;
+    use EventsLoop;
+
+    #[test]
+    fn suppression_rate_matches_the_key_distribution() {
+        // 10 000 distinct keys, each sent three times.
+        let events = (0..30_000u32).map(|n| n % 10_000).collect::<Vec<_>>();
+        let mut dedup = BloomDedup::new(MockEventsLoop::new(events), |&n: &u32| n, 10_000, 0.01);
+
+        let mut delivered = 0;
+        dedup.poll_events(&mut |_| delivered += 1);
+
+        // Every repeat is suppressed, and about 1% of the first occurrences are mistaken for
+        // repeats.
+        let false_positives = 10_000 - delivered;
+        assert_eq!(dedup.suppressed(), 20_000 + false_positives);
+        assert!(false_positives < 200, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn reset_forgets_the_seen_keys() {
+        let mut dedup = BloomDedup::new(MockEventsLoop::new(vec![1, 1]), |&n: &u32| n, 100, 0.01);
+        let mut delivered = Vec::new();
+        dedup.poll_events(&mut |n| delivered.push(n));
+        assert_eq!(delivered, vec![1]);
+
+        dedup.inner.push(1);
+        dedup.poll_events(&mut |n| delivered.push(n));
+        assert_eq!(delivered, vec![1]);
+
+        dedup.reset();
+        dedup.inner.push(1);
+        dedup.poll_events(&mut |n| delivered.push(n));
+        assert_eq!(delivered, vec![1, 1]);
+    }
+}
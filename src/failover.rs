@@ -0,0 +1,293 @@
+//! Switching to a backup loop once the primary one closes.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use {ControlFlow, EventsLoop, EventsLoopClosed, EventsLoopProxy};
+
+/// Delivers the events of a primary loop until it closes, then those of a backup loop.
+///
+/// The primary loop is considered gone once `EventsLoop::is_closed` returns `true`, which is
+/// checked whenever it returns from `poll_events` or `run`. The channel and bounded loops of
+/// this crate close once every one of their proxies is gone, and `run` then returns on its own,
+/// so the switch happens as soon as the producers of the primary loop go away. The switch is
+/// transparent to the callback, which keeps receiving events from the backup loop, optionally
+/// preceded by a synthetic event given to `with_failover_event` to mark the transition. There is
+/// no way back to the primary loop.
+///
+/// The proxies returned by `create_proxy` wake up whichever loop is active at the time of the
+/// wakeup, and fall back to the backup loop if the primary one is already gone. They don't count
+/// as producers of the primary loop, see `EventsLoop::create_wakeup_proxy`, so they don't delay
+/// the switch.
+pub struct Failover<A, B, Event> {
+    primary: A,
+    backup: B,
+    failed_over: Arc<AtomicBool>,
+    failover_event: Option<Event>,
+}
+
+impl<A, B, Event> Failover<A, B, Event>
+where
+    A: EventsLoop<Event>,
+    B: EventsLoop<Event>,
+{
+    /// Builds the adapter, starting with `primary`.
+    pub fn new(primary: A, backup: B) -> Failover<A, B, Event> {
+        Failover {
+            primary,
+            backup,
+            failed_over: Arc::new(AtomicBool::new(false)),
+            failover_event: None,
+        }
+    }
+
+    /// Delivers `event` to the callback right after switching to the backup loop.
+    pub fn with_failover_event(mut self, event: Event) -> Failover<A, B, Event> {
+        self.failover_event = Some(event);
+        self
+    }
+
+    /// Returns `true` once the backup loop has taken over.
+    pub fn is_failed_over(&self) -> bool {
+        self.failed_over.load(Ordering::SeqCst)
+    }
+
+    /// Returns the primary and backup loops.
+    pub fn into_inner(self) -> (A, B) {
+        (self.primary, self.backup)
+    }
+
+    // Switches to the backup loop if the primary one is closed, and returns the failover event
+    // to deliver, if any.
+    fn check_primary(&mut self) -> Option<Event> {
+        if self.is_failed_over() || !self.primary.is_closed() {
+            return None;
+        }
+        self.failed_over.store(true, Ordering::SeqCst);
+        self.failover_event.take()
+    }
+}
+
+impl<A, B, Event> EventsLoop<Event> for Failover<A, B, Event>
+where
+    A: EventsLoop<Event>,
+    B: EventsLoop<Event>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        if !self.is_failed_over() {
+            self.primary.poll_events(callback);
+            let failover_event = self.check_primary();
+            if !self.is_failed_over() {
+                return;
+            }
+            if let Some(event) = failover_event {
+                callback(event);
+            }
+        }
+        self.backup.poll_events(callback);
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        if !self.is_failed_over() {
+            let mut exited = false;
+            self.primary.run(&mut |event| {
+                let flow = callback(event);
+                exited = flow.is_exit();
+                flow
+            });
+            if exited {
+                return;
+            }
+            let failover_event = self.check_primary();
+            if !self.is_failed_over() {
+                return;
+            }
+            if let Some(event) = failover_event {
+                if callback(event).is_exit() {
+                    return;
+                }
+            }
+        }
+        self.backup.run(callback);
+    }
+
+    /// The proxy wakes the primary loop up through `EventsLoop::create_wakeup_proxy`, so that it
+    /// doesn't keep the primary loop open.
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(FailoverProxy {
+            primary: self.primary.create_wakeup_proxy(),
+            backup: self.backup.create_proxy(),
+            failed_over: self.failed_over.clone(),
+        })
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(FailoverProxy {
+            primary: self.primary.create_wakeup_proxy(),
+            backup: self.backup.create_wakeup_proxy(),
+            failed_over: self.failed_over.clone(),
+        })
+    }
+
+    /// Returns the count of the loop that is active.
+    fn pending_count(&self) -> Option<usize> {
+        if self.is_failed_over() {
+            self.backup.pending_count()
+        } else {
+            self.primary.pending_count()
+        }
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        if self.is_failed_over() {
+            self.backup.remaining_capacity()
+        } else {
+            self.primary.remaining_capacity()
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.is_failed_over() && self.backup.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        if self.is_failed_over() {
+            self.backup.is_running()
+        } else {
+            self.primary.is_running()
+        }
+    }
+}
+
+// Wakes up the primary loop until the failover, and the backup loop afterwards.
+struct FailoverProxy {
+    primary: Box<dyn EventsLoopProxy>,
+    backup: Box<dyn EventsLoopProxy>,
+    failed_over: Arc<AtomicBool>,
+}
+
+impl EventsLoopProxy for FailoverProxy {
+    fn wakeup(&self) -> Result<(), EventsLoopClosed> {
+        if !self.failed_over.load(Ordering::SeqCst) && self.primary.wakeup().is_ok() {
+            return Ok(());
+        }
+        self.backup.wakeup()
+    }
+
+    fn clone(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(FailoverProxy {
+            primary: EventsLoopProxy::clone(&*self.primary),
+            backup: EventsLoopProxy::clone(&*self.backup),
+            failed_over: self.failed_over.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::Failover;
+    use bounded::BoundedEventsLoop;
+    use channel::ChannelEventsLoop;
+    use mock::{Event, MockEventsLoop};
+    use {ControlFlow, EventsLoop, EventsLoopPayloadProxy, OverflowPolicy};
+
+    #[test]
+    fn backup_takes_over_once_the_producers_of_the_primary_are_gone() {
+        let primary = ChannelEventsLoop::new();
+        let backup = ChannelEventsLoop::new();
+        let proxy = primary.create_payload_proxy();
+        let backup_proxy = backup.create_payload_proxy();
+        backup_proxy.wakeup_with(Event::Data(10)).unwrap();
+        let mut l = Failover::new(primary, backup).with_failover_event(Event::Data(0));
+
+        let producer = thread::spawn(move || {
+            proxy.wakeup_with(Event::Data(1)).unwrap();
+            proxy.wakeup_with(Event::Data(2)).unwrap();
+        });
+        let mut delivered = Vec::new();
+        l.run(&mut |e| {
+            let flow = if e == Event::Data(10) {
+                ControlFlow::Break
+            } else {
+                ControlFlow::Continue
+            };
+            delivered.push(e);
+            flow
+        });
+        producer.join().unwrap();
+
+        assert_eq!(
+            delivered,
+            vec![1, 2, 0, 10]
+                .into_iter()
+                .map(Event::Data)
+                .collect::<Vec<_>>()
+        );
+        assert!(l.is_failed_over());
+        assert!(!l.is_closed());
+    }
+
+    #[test]
+    fn its_proxies_dont_keep_the_primary_open() {
+        let primary = ChannelEventsLoop::new();
+        let backup = BoundedEventsLoop::with_capacity(4, OverflowPolicy::Reject);
+        let proxy = primary.create_payload_proxy();
+        let _backup_proxy = backup.create_payload_proxy();
+        let mut l = Failover::new(primary, backup);
+        let wakeup = l.create_proxy();
+
+        wakeup.wakeup().unwrap();
+        proxy.wakeup_with(Event::Data(1)).unwrap();
+        drop(proxy);
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered, vec![Event::Awakened, Event::Data(1)]);
+        assert!(l.is_failed_over());
+
+        wakeup.wakeup().unwrap();
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered.last(), Some(&Event::Awakened));
+        assert_eq!(delivered.len(), 3);
+    }
+
+    #[test]
+    fn backup_takes_over_when_the_primary_closes() {
+        let mut l = Failover::new(
+            MockEventsLoop::new(vec![1, 2, 3]),
+            MockEventsLoop::new(vec![10, 11]),
+        )
+        .with_failover_event(0);
+
+        let mut delivered = Vec::new();
+        l.run(&mut |e| {
+            delivered.push(e);
+            ControlFlow::Continue
+        });
+        assert_eq!(delivered, vec![1, 2, 3, 0, 10, 11]);
+        assert!(l.is_failed_over());
+        assert!(l.is_closed());
+    }
+
+    #[test]
+    fn primary_keeps_delivering_until_it_closes() {
+        let mut l = Failover::new(
+            MockEventsLoop::new(vec![1, 2]),
+            MockEventsLoop::new(vec![10]),
+        );
+
+        let mut delivered = Vec::new();
+        l.run(&mut |e| {
+            delivered.push(e);
+            ControlFlow::Break
+        });
+        assert_eq!(delivered, vec![1]);
+        assert!(!l.is_failed_over());
+        assert_eq!(l.pending_count(), Some(1));
+
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered, vec![1, 2, 10]);
+        assert!(l.is_failed_over());
+    }
+}
@@ -0,0 +1,144 @@
+//! Exposing an `EventsLoop` to C, as an opaque context and `extern "C"` function pointers.
+//!
+//! Only available with the `ffi` feature.
+
+use std::os::raw::{c_int, c_void};
+
+use {ControlFlow, EventsLoop};
+
+/// Callback invoked by the trampolines of a `RawLoop` for each event.
+///
+/// `user_data` is the pointer given to the trampoline, and `event` points to the event, which is
+/// only valid for the duration of the call. When called from `RawLoop::run`, returning `0`
+/// continues the loop and any other value breaks out of it. The return value is ignored when
+/// called from `RawLoop::poll`.
+pub type RawEventCallback =
+    unsafe extern "C" fn(user_data: *mut c_void, event: *const c_void) -> c_int;
+
+/// An `EventsLoop` turned into an opaque context and C-compatible function pointers.
+///
+/// Every function must be given `context`, and `free` must be called exactly once when the host
+/// is done with the loop. See `into_raw_callbacks` for the full safety contract.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RawLoop {
+    /// The boxed loop, to be passed back to the functions below.
+    pub context: *mut c_void,
+    /// Calls `EventsLoop::poll_events`, passing each event to `callback` along with `user_data`.
+    pub poll: unsafe extern "C" fn(
+        context: *mut c_void,
+        callback: RawEventCallback,
+        user_data: *mut c_void,
+    ),
+    /// Calls `EventsLoop::run`, passing each event to `callback` along with `user_data`.
+    pub run: unsafe extern "C" fn(
+        context: *mut c_void,
+        callback: RawEventCallback,
+        user_data: *mut c_void,
+    ),
+    /// Drops the loop. The context must not be used afterwards.
+    pub free: unsafe extern "C" fn(context: *mut c_void),
+}
+
+/// Moves `events_loop` to the heap and returns it as a `RawLoop`.
+///
+/// # Safety contract
+///
+/// Building the `RawLoop` is safe, but calling its functions is not. The host must uphold the
+/// following:
+///
+/// - `context` is only ever passed to the functions of the same `RawLoop`, and not after `free`
+///   has been called. `free` is called exactly once, or the loop is leaked.
+/// - The functions are called from the thread that built the `RawLoop`, unless the loop is
+///   `Send`, and never concurrently or reentrantly, for instance from inside `callback`.
+/// - `callback` reads the event through `event` as the type `Event` it actually is, which in
+///   practice means that `Event` is `#[repr(C)]` or that the host treats it as opaque. The event
+///   is dropped once `callback` returns, so the pointer must not be kept.
+/// - `callback` doesn't unwind. Likewise, a panic in the loop aborts the process instead of
+///   unwinding into the host.
+pub fn into_raw_callbacks<L, Event>(events_loop: L) -> RawLoop
+where
+    L: EventsLoop<Event> + 'static,
+{
+    RawLoop {
+        context: Box::into_raw(Box::new(events_loop)) as *mut c_void,
+        poll: poll_trampoline::<L, Event>,
+        run: run_trampoline::<L, Event>,
+        free: free_trampoline::<L>,
+    }
+}
+
+/// Drops the loop of `raw`, like calling `raw.free` would.
+///
+/// # Safety
+///
+/// `raw` must come from `into_raw_callbacks`, and its `free` function must not have been called.
+pub unsafe fn free(raw: RawLoop) {
+    (raw.free)(raw.context);
+}
+
+unsafe extern "C" fn poll_trampoline<L, Event>(
+    context: *mut c_void,
+    callback: RawEventCallback,
+    user_data: *mut c_void,
+) where
+    L: EventsLoop<Event>,
+{
+    let events_loop = &mut *(context as *mut L);
+    events_loop.poll_events(&mut |event| {
+        callback(user_data, &event as *const Event as *const c_void);
+    });
+}
+
+unsafe extern "C" fn run_trampoline<L, Event>(
+    context: *mut c_void,
+    callback: RawEventCallback,
+    user_data: *mut c_void,
+) where
+    L: EventsLoop<Event>,
+{
+    let events_loop = &mut *(context as *mut L);
+    events_loop.run(
+        &mut |event| match callback(user_data, &event as *const Event as *const c_void) {
+            0 => ControlFlow::Continue,
+            _ => ControlFlow::Break,
+        },
+    );
+}
+
+unsafe extern "C" fn free_trampoline<L>(context: *mut c_void) {
+    drop(Box::from_raw(context as *mut L));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::raw::{c_int, c_void};
+
+    use super::{free, into_raw_callbacks};
+    use mock::MockEventsLoop;
+
+    // Stands in for the C side: collects the events into the `Vec<u32>` behind `user_data`, and
+    // breaks after the third one.
+    unsafe extern "C" fn collect(user_data: *mut c_void, event: *const c_void) -> c_int {
+        let delivered = &mut *(user_data as *mut Vec<u32>);
+        delivered.push(*(event as *const u32));
+        (delivered.len() == 3) as c_int
+    }
+
+    #[test]
+    fn round_trip_through_the_c_abi() {
+        let raw = into_raw_callbacks(MockEventsLoop::new(vec![1u32, 2, 3, 4]));
+        let mut delivered = Vec::<u32>::new();
+        let user_data = &mut delivered as *mut Vec<u32> as *mut c_void;
+
+        unsafe {
+            (raw.run)(raw.context, collect, user_data);
+            assert_eq!(delivered, vec![1, 2, 3]);
+
+            (raw.poll)(raw.context, collect, user_data);
+            assert_eq!(delivered, vec![1, 2, 3, 4]);
+
+            free(raw);
+        }
+    }
+}
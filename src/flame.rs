@@ -0,0 +1,209 @@
+//! Sampling which event handlers the loop spends its time in, as a flamegraph.
+//!
+//! Only available with the `profiling` feature.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+/// The root frame of every stack in the output of `FlameTrace::folded`.
+pub const ROOT_FRAME: &str = "events_loop";
+
+#[derive(Default)]
+struct Samples {
+    // The label of the event being handled, if any.
+    current: Option<String>,
+    counts: BTreeMap<String, u64>,
+}
+
+// Marks `label` as the handler being executed until the guard is dropped, including when the
+// callback panics.
+struct Executing<'s> {
+    samples: &'s Mutex<Samples>,
+}
+
+impl<'s> Executing<'s> {
+    fn enter(samples: &'s Mutex<Samples>, label: String) -> Executing<'s> {
+        samples.lock().unwrap().current = Some(label);
+        Executing { samples }
+    }
+}
+
+impl<'s> Drop for Executing<'s> {
+    fn drop(&mut self) {
+        if let Ok(mut samples) = self.samples.lock() {
+            samples.current = None;
+        }
+    }
+}
+
+/// Wraps an `EventsLoop` and, together with a sampling timer, measures which event handlers
+/// dominate the time of the loop.
+///
+/// Every event is given a label by the labeler passed to `new`, for example the name of its
+/// variant, and the adapter records the label of the event being handled while the callback
+/// runs. The timer, typically a thread that ticks at a fixed rate, calls `FlameSampler::sample`
+/// on every tick, which counts one sample for the handler that is executing at that moment, or
+/// for the loop itself while it is idle. `folded` produces the counts in the folded stack
+/// format that `inferno` and `flamegraph.pl` turn into a flamegraph, with a stack of
+/// `events_loop;<label>` per handler, so the width of each handler matches its share of the
+/// time.
+///
+/// The labels are sanitized for the format: semicolons and line breaks become underscores.
+pub struct FlameTrace<'a, L, Event> {
+    inner: L,
+    label: Box<dyn FnMut(&Event) -> String + 'a>,
+    samples: Arc<Mutex<Samples>>,
+}
+
+/// Takes samples for a `FlameTrace` from any thread.
+#[derive(Clone)]
+pub struct FlameSampler {
+    samples: Arc<Mutex<Samples>>,
+}
+
+impl FlameSampler {
+    /// Counts one sample for the handler executing right now.
+    pub fn sample(&self) {
+        let mut samples = self.samples.lock().unwrap();
+        let stack = match samples.current {
+            Some(ref label) => format!("{};{}", ROOT_FRAME, label),
+            None => ROOT_FRAME.to_owned(),
+        };
+        *samples.counts.entry(stack).or_insert(0) += 1;
+    }
+}
+
+impl<'a, L, Event> FlameTrace<'a, L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    /// Builds the adapter around `inner`, labeling the events with `label`.
+    pub fn new<F>(inner: L, label: F) -> FlameTrace<'a, L, Event>
+    where
+        F: FnMut(&Event) -> String + 'a,
+    {
+        FlameTrace {
+            inner,
+            label: Box::new(label),
+            samples: Arc::default(),
+        }
+    }
+
+    /// Returns a sampler for the timer, which can be sent to another thread.
+    pub fn sampler(&self) -> FlameSampler {
+        FlameSampler {
+            samples: self.samples.clone(),
+        }
+    }
+
+    /// Returns the samples taken so far in the folded stack format, one `stack count` line per
+    /// stack, sorted by stack.
+    pub fn folded(&self) -> String {
+        let samples = self.samples.lock().unwrap();
+        let mut folded = String::new();
+        for (stack, count) in &samples.counts {
+            let _ = writeln!(folded, "{} {}", stack, count);
+        }
+        folded
+    }
+
+    /// Forgets the samples taken so far.
+    pub fn reset(&self) {
+        self.samples.lock().unwrap().counts.clear();
+    }
+
+    /// Returns the wrapped loop.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+fn sanitize(label: &str) -> String {
+    label.replace([';', '\n', '\r'], "_")
+}
+
+impl<'a, L, Event> EventsLoop<Event> for FlameTrace<'a, L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let FlameTrace {
+            ref mut inner,
+            ref mut label,
+            ref samples,
+        } = *self;
+        inner.poll_events(&mut |event| {
+            let _executing = Executing::enter(samples, sanitize(&label(&event)));
+            callback(event);
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let FlameTrace {
+            ref mut inner,
+            ref mut label,
+            ref samples,
+        } = *self;
+        inner.run(&mut |event| {
+            let _executing = Executing::enter(samples, sanitize(&label(&event)));
+            callback(event)
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FlameTrace;
+    use mock::MockEventsLoop;
+    use EventsLoop;
+
+    #[test]
+    fn samples_are_folded_per_handler() {
+        let events = vec!["resize", "key", "resize", "redraw;full"];
+        let mut l = FlameTrace::new(MockEventsLoop::new(events), |e: &&str| e.to_string());
+        let sampler = l.sampler();
+
+        // Stands in for the timer: the handlers take as many ticks as their name is long.
+        sampler.sample();
+        l.poll_events(&mut |e| {
+            for _ in 0..e.len() {
+                sampler.sample();
+            }
+        });
+
+        assert_eq!(
+            l.folded(),
+            "events_loop 1\n\
+             events_loop;key 3\n\
+             events_loop;redraw_full 11\n\
+             events_loop;resize 12\n"
+        );
+    }
+}
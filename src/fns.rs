@@ -0,0 +1,161 @@
+//! Building an `EventsLoop` out of closures.
+
+use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+type PollFn<'a, Event> = Box<dyn FnMut(&mut dyn FnMut(Event)) + 'a>;
+type RunFn<'a, Event> = Box<dyn FnMut(&mut dyn FnMut(Event) -> ControlFlow) + 'a>;
+type ProxyFn<'a> = Box<dyn Fn() -> Box<dyn EventsLoopProxy> + 'a>;
+type CountFn<'a> = Box<dyn Fn() -> Option<usize> + 'a>;
+
+/// Builds an `EventsLoop` whose methods call the given closures.
+///
+/// `poll` and `run` implement `EventsLoop::poll_events` and `EventsLoop::run`, and `proxy`
+/// implements `EventsLoop::create_proxy`. This spares the boilerplate of a trait implementation
+/// for one-off loops and tests.
+///
+/// The closures are stored in the returned `FnLoop`, along with whatever they capture. Each one
+/// owns its captures, so state that both `poll` and `run` need has to be shared between them,
+/// typically through an `Rc<RefCell<_>>`. The loop borrows anything the closures borrow, and as
+/// such can't outlive it: capture by `move` to get an `FnLoop<'static, Event>`.
+///
+/// ```
+/// use std::cell::RefCell;
+/// use std::collections::VecDeque;
+/// use std::rc::Rc;
+///
+/// use events_loop::fns::from_fns;
+/// use events_loop::{ControlFlow, EventsLoop, EventsLoopClosed, EventsLoopProxy};
+///
+/// // `run` returns as soon as the queue is empty, so there's never anything to wake up.
+/// struct NoWakeup;
+///
+/// impl EventsLoopProxy for NoWakeup {
+///     fn wakeup(&self) -> Result<(), EventsLoopClosed> {
+///         Ok(())
+///     }
+///
+///     fn clone(&self) -> Box<dyn EventsLoopProxy> {
+///         Box::new(NoWakeup)
+///     }
+/// }
+///
+/// let queue = Rc::new(RefCell::new(VecDeque::from(vec![1, 2, 3])));
+/// let (polled, ran) = (queue.clone(), queue.clone());
+/// let mut l = from_fns(
+///     move |callback: &mut dyn FnMut(i32)| {
+///         while let Some(event) = polled.borrow_mut().pop_front() {
+///             callback(event);
+///         }
+///     },
+///     move |callback: &mut dyn FnMut(i32) -> ControlFlow| {
+///         while let Some(event) = ran.borrow_mut().pop_front() {
+///             if callback(event).is_exit() {
+///                 return;
+///             }
+///         }
+///     },
+///     || Box::new(NoWakeup) as Box<dyn EventsLoopProxy>,
+/// );
+///
+/// let mut sum = 0;
+/// l.poll_events(&mut |n| sum += n);
+/// assert_eq!(sum, 6);
+/// assert!(l.create_proxy().wakeup().is_ok());
+/// ```
+pub fn from_fns<'a, Event, P, R, C>(poll: P, run: R, proxy: C) -> FnLoop<'a, Event>
+where
+    P: FnMut(&mut dyn FnMut(Event)) + 'a,
+    R: FnMut(&mut dyn FnMut(Event) -> ControlFlow) + 'a,
+    C: Fn() -> Box<dyn EventsLoopProxy> + 'a,
+{
+    FnLoop {
+        poll: Box::new(poll),
+        run: Box::new(run),
+        proxy: Box::new(proxy),
+        pending_count: None,
+    }
+}
+
+/// An `EventsLoop` built out of closures by `from_fns`.
+pub struct FnLoop<'a, Event> {
+    poll: PollFn<'a, Event>,
+    run: RunFn<'a, Event>,
+    proxy: ProxyFn<'a>,
+    pending_count: Option<CountFn<'a>>,
+}
+
+impl<'a, Event> FnLoop<'a, Event> {
+    /// Implements `EventsLoop::pending_count` with `f`, which otherwise returns `None`.
+    pub fn with_pending_count<F>(mut self, f: F) -> FnLoop<'a, Event>
+    where
+        F: Fn() -> Option<usize> + 'a,
+    {
+        self.pending_count = Some(Box::new(f));
+        self
+    }
+}
+
+impl<'a, Event> EventsLoop<Event> for FnLoop<'a, Event> {
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        (self.poll)(callback)
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        (self.run)(callback)
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        (self.proxy)()
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.pending_count.as_ref().and_then(|f| f())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::from_fns;
+    use channel::ChannelEventsLoop;
+    use mock::Event;
+    use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+    #[test]
+    fn closures_keep_their_captured_state() {
+        let backend = ChannelEventsLoop::<Event>::new();
+        let proxy = backend.create_payload_proxy();
+        let runs = Cell::new(0);
+        let mut l = from_fns(
+            |callback: &mut dyn FnMut(u32)| callback(0),
+            |callback: &mut dyn FnMut(u32) -> ControlFlow| {
+                let mut n = 0;
+                while !callback(n).is_exit() {
+                    n += 1;
+                }
+                runs.set(runs.get() + 1);
+            },
+            move || EventsLoopProxy::clone(&proxy),
+        )
+        .with_pending_count(|| Some(runs.get()));
+
+        let mut delivered = Vec::new();
+        l.run(&mut |n| {
+            delivered.push(n);
+            if n == 2 {
+                ControlFlow::Break
+            } else {
+                ControlFlow::Continue
+            }
+        });
+        l.poll_events(&mut |n| delivered.push(n));
+        assert_eq!(delivered, vec![0, 1, 2, 0]);
+        assert_eq!(l.pending_count(), Some(1));
+
+        l.create_proxy().wakeup().unwrap();
+        drop(l);
+        assert_eq!(runs.get(), 1);
+        assert_eq!(backend.pending_count(), Some(1));
+    }
+}
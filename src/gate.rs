@@ -0,0 +1,228 @@
+//! Pausing and resuming event delivery at runtime.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use budget::MemoryBudget;
+use queue::BoundedQueue;
+pub use queue::DEFAULT_CAPACITY;
+use {ControlFlow, DropSink, EventsLoop, EventsLoopProxy, OverflowPolicy};
+
+/// Wraps an `EventsLoop` and holds its events back while paused.
+///
+/// The gate is controlled through a `GateHandle`, which can be sent to other threads. While the
+/// gate is paused, the events of the inner loop are buffered instead of delivered. Once it is
+/// resumed, the buffered events are delivered first, in order, before the new ones. The buffer
+/// is bounded, and the `OverflowPolicy` decides which events are discarded once it is full, see
+/// `DropSink` and `MemoryBudget`.
+///
+/// The handle only flips a flag: while `run` is blocked waiting for an event, resuming takes
+/// effect when the next event arrives, which a wakeup through a proxy can force.
+pub struct Gate<L, Event> {
+    inner: L,
+    paused: Arc<AtomicBool>,
+    buffer: BoundedQueue<Event>,
+}
+
+/// Pauses and resumes a `Gate` from any thread.
+#[derive(Clone, Debug)]
+pub struct GateHandle {
+    paused: Arc<AtomicBool>,
+}
+
+impl GateHandle {
+    /// Starts buffering events instead of delivering them.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resumes delivery, starting with the buffered events.
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if the gate is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+}
+
+impl<L, Event> Gate<L, Event> {
+    /// Builds an open gate around `inner`.
+    pub fn new(inner: L) -> Gate<L, Event> {
+        Gate {
+            inner,
+            paused: Arc::new(AtomicBool::new(false)),
+            buffer: BoundedQueue::new(DEFAULT_CAPACITY, OverflowPolicy::DropOldest),
+        }
+    }
+
+    /// Bounds the buffer to `capacity` events, applying `policy` when it is full.
+    pub fn with_capacity(mut self, capacity: usize, policy: OverflowPolicy) -> Gate<L, Event> {
+        self.buffer.set_bounds(capacity, policy);
+        self
+    }
+
+    /// Reports the events discarded from the buffer to `sink`, see `DropSink`.
+    pub fn with_drop_sink(mut self, sink: DropSink<Event>) -> Gate<L, Event> {
+        self.buffer.set_drop_sink(sink);
+        self
+    }
+
+    /// Draws the memory of the buffered events from `budget`, see `MemoryBudget`.
+    pub fn with_memory_budget(mut self, budget: MemoryBudget<Event>) -> Gate<L, Event> {
+        self.buffer.set_budget(budget);
+        self
+    }
+
+    /// Returns a handle controlling the gate.
+    pub fn handle(&self) -> GateHandle {
+        GateHandle {
+            paused: self.paused.clone(),
+        }
+    }
+
+    /// Returns the number of events held back.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+impl<L, Event> EventsLoop<Event> for Gate<L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let paused = &self.paused;
+        let buffer = &mut self.buffer;
+        self.inner.poll_events(&mut |event| {
+            if paused.load(Ordering::SeqCst) {
+                buffer.push(event);
+                return;
+            }
+            while let Some(buffered) = buffer.pop() {
+                callback(buffered);
+            }
+            callback(event);
+        });
+        if !paused.load(Ordering::SeqCst) {
+            while let Some(buffered) = buffer.pop() {
+                callback(buffered);
+            }
+        }
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let paused = &self.paused;
+        let buffer = &mut self.buffer;
+        // Events held back before the gate was resumed don't wait for the next one.
+        if !paused.load(Ordering::SeqCst) {
+            while let Some(buffered) = buffer.pop() {
+                if callback(buffered).is_exit() {
+                    return;
+                }
+            }
+        }
+        self.inner.run(&mut |event| {
+            if paused.load(Ordering::SeqCst) {
+                buffer.push(event);
+                return ControlFlow::Continue;
+            }
+            while let Some(buffered) = buffer.pop() {
+                let flow = callback(buffered);
+                if flow.is_exit() {
+                    buffer.push(event);
+                    return flow;
+                }
+            }
+            callback(event)
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    /// Returns the headroom of the buffer, or that of the wrapped loop if it is smaller.
+    fn remaining_capacity(&self) -> Option<usize> {
+        let own = self.buffer.headroom();
+        Some(
+            self.inner
+                .remaining_capacity()
+                .map_or(own, |inner| inner.min(own)),
+        )
+    }
+
+    /// Returns `true` once the inner loop is closed and the buffer is empty.
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed() && self.buffer.is_empty()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    /// Counts the buffered events along with those pending in the inner loop.
+    fn pending_count(&self) -> Option<usize> {
+        self.inner
+            .pending_count()
+            .map(|pending| pending + self.buffer.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::Gate;
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop, OverflowPolicy};
+
+    #[test]
+    fn buffered_events_flush_in_order_on_resume() {
+        let mut gate = Gate::new(MockEventsLoop::new(vec![1, 2]));
+        let handle = gate.handle();
+        let mut delivered = Vec::new();
+
+        thread::spawn(move || handle.pause()).join().unwrap();
+        gate.poll_events(&mut |e| delivered.push(e));
+        assert!(delivered.is_empty());
+        assert_eq!(gate.buffered(), 2);
+
+        gate.handle().resume();
+        gate.run(&mut |e| {
+            delivered.push(e);
+            ControlFlow::Continue
+        });
+        assert_eq!(delivered, vec![1, 2]);
+        assert_eq!(gate.buffered(), 0);
+    }
+
+    #[test]
+    fn overflow_policy_bounds_the_buffer() {
+        let mut gate = Gate::new(MockEventsLoop::new(vec![1, 2, 3, 4]))
+            .with_capacity(2, OverflowPolicy::DropOldest);
+        gate.handle().pause();
+        gate.poll_events(&mut |_| panic!("delivered while paused"));
+
+        gate.handle().resume();
+        let mut delivered = Vec::new();
+        gate.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered, vec![3, 4]);
+    }
+
+    #[test]
+    fn the_buffer_bounds_the_remaining_capacity() {
+        let mut gate =
+            Gate::new(MockEventsLoop::new(vec![1, 2])).with_capacity(3, OverflowPolicy::DropOldest);
+        assert_eq!(gate.remaining_capacity(), Some(3));
+
+        gate.handle().pause();
+        gate.poll_events(&mut |_| panic!("delivered while paused"));
+        assert_eq!(gate.remaining_capacity(), Some(1));
+    }
+}
@@ -0,0 +1,170 @@
+//! Detecting a loop thread that stopped processing events.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+struct Beats {
+    start: Instant,
+    // Nanoseconds from `start` to the last beat.
+    last: AtomicU64,
+    busy: AtomicBool,
+}
+
+impl Beats {
+    fn beat(&self, busy: bool) {
+        let nanos = self.start.elapsed().as_nanos() as u64;
+        self.last.store(nanos, Ordering::Release);
+        self.busy.store(busy, Ordering::Release);
+    }
+}
+
+/// Wraps an `EventsLoop` and records a heartbeat every time it delivers an event, so that another
+/// thread can tell, through a `HeartbeatMonitor`, whether the loop thread hung.
+///
+/// The loop beats when the callback is called and when it returns, as well as on every call to
+/// `poll_events`. In between, the loop is either busy in the callback or idle, waiting for
+/// events in `run`. An idle loop is healthy however long it waits, so only a callback that runs
+/// for too long, for example because it is deadlocked, counts as a stall.
+pub struct Heartbeat<L> {
+    inner: L,
+    beats: Arc<Beats>,
+}
+
+impl<L> Heartbeat<L> {
+    /// Builds the adapter around `inner`. It starts idle, with a first beat.
+    pub fn new(inner: L) -> Heartbeat<L> {
+        Heartbeat {
+            inner,
+            beats: Arc::new(Beats {
+                start: Instant::now(),
+                last: AtomicU64::new(0),
+                busy: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Returns a monitor of the heartbeat, which can be sent to another thread.
+    pub fn monitor(&self) -> HeartbeatMonitor {
+        HeartbeatMonitor {
+            beats: self.beats.clone(),
+        }
+    }
+
+    /// Returns the wrapped loop.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+impl<L, Event> EventsLoop<Event> for Heartbeat<L>
+where
+    L: EventsLoop<Event>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let beats = &*self.beats;
+        beats.beat(false);
+        self.inner.poll_events(&mut |event| {
+            beats.beat(true);
+            callback(event);
+            beats.beat(false);
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let beats = &*self.beats;
+        self.inner.run(&mut |event| {
+            beats.beat(true);
+            let flow = callback(event);
+            beats.beat(false);
+            flow
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.pending_count()
+    }
+}
+
+/// Watches the heartbeat of a `Heartbeat` loop from any thread.
+#[derive(Clone)]
+pub struct HeartbeatMonitor {
+    beats: Arc<Beats>,
+}
+
+impl HeartbeatMonitor {
+    /// Returns the instant of the last beat.
+    pub fn last_beat(&self) -> Instant {
+        let nanos = self.beats.last.load(Ordering::Acquire);
+        self.beats.start + Duration::from_nanos(nanos)
+    }
+
+    /// Returns `true` if the loop has been busy in its callback for longer than `threshold`
+    /// since the last beat.
+    pub fn is_stalled(&self, threshold: Duration) -> bool {
+        // Read the flag first, so that a beat in between can only make the loop look healthy.
+        let busy = self.beats.busy.load(Ordering::Acquire);
+        busy && self.last_beat().elapsed() > threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::Heartbeat;
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop};
+
+    #[test]
+    fn hung_callback_is_detected() {
+        let threshold = Duration::from_millis(20);
+        let mut l = Heartbeat::new(MockEventsLoop::new(vec![1, 2, 3]));
+        let monitor = l.monitor();
+        assert!(!monitor.is_stalled(Duration::from_secs(0)));
+
+        let (release, hang) = mpsc::channel::<()>();
+        let looper = thread::spawn(move || {
+            l.run(&mut |n| {
+                if n == 2 {
+                    hang.recv().unwrap();
+                }
+                ControlFlow::Continue
+            });
+        });
+
+        let give_up = Instant::now() + Duration::from_secs(10);
+        while !monitor.is_stalled(threshold) {
+            assert!(Instant::now() < give_up, "the hang went unnoticed");
+            thread::sleep(threshold / 4);
+        }
+
+        release.send(()).unwrap();
+        looper.join().unwrap();
+        assert!(!monitor.is_stalled(threshold));
+    }
+}
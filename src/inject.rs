@@ -0,0 +1,101 @@
+//! Injecting derived events from inside the callback.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+/// A handle that queues events to be delivered right after the current callback returns.
+///
+/// The callback given to `EventsLoop::run_with_injector` receives an `Injector` next to each
+/// event. Events pushed into it are not delivered recursively, which would conflict with the
+/// borrows held by the callback: they are queued, and delivered once the current callback has
+/// returned, before the loop goes back to the system queue or blocks again.
+pub struct Injector<Event> {
+    queue: Rc<RefCell<VecDeque<Event>>>,
+}
+
+impl<Event> Clone for Injector<Event> {
+    fn clone(&self) -> Injector<Event> {
+        Injector {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<Event> Default for Injector<Event> {
+    fn default() -> Injector<Event> {
+        Injector::new()
+    }
+}
+
+impl<Event> Injector<Event> {
+    /// Builds an empty injector.
+    pub fn new() -> Injector<Event> {
+        Injector {
+            queue: Rc::new(RefCell::new(VecDeque::new())),
+        }
+    }
+
+    /// Queues `event` behind the events already injected.
+    pub fn push(&self, event: Event) {
+        self.queue.borrow_mut().push_back(event);
+    }
+
+    /// Returns the number of injected events that haven't been delivered yet.
+    pub fn len(&self) -> usize {
+        self.queue.borrow().len()
+    }
+
+    /// Returns `true` if there are no injected events left to deliver.
+    pub fn is_empty(&self) -> bool {
+        self.queue.borrow().is_empty()
+    }
+
+    pub(crate) fn pop(&self) -> Option<Event> {
+        self.queue.borrow_mut().pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop};
+
+    #[test]
+    fn injected_events_are_delivered_before_the_next_one() {
+        let mut l = MockEventsLoop::new(vec![1, 2]);
+        let mut delivered = Vec::new();
+        l.run_with_injector(&mut |event, injector| {
+            delivered.push(event);
+            match event {
+                1 => {
+                    injector.push(10);
+                    injector.push(11);
+                }
+                10 => injector.push(100),
+                _ => {}
+            }
+            ControlFlow::Continue
+        });
+        assert_eq!(delivered, vec![1, 10, 11, 100, 2]);
+    }
+
+    #[test]
+    fn exit_drops_the_remaining_injected_events() {
+        let mut l = MockEventsLoop::new(vec![1, 2]);
+        let mut delivered = Vec::new();
+        l.run_with_injector(&mut |event, injector| {
+            delivered.push(event);
+            if event == 1 {
+                injector.push(10);
+                injector.push(11);
+            }
+            if event == 10 {
+                ControlFlow::Break
+            } else {
+                ControlFlow::Continue
+            }
+        });
+        assert_eq!(delivered, vec![1, 10]);
+    }
+}
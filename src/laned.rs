@@ -0,0 +1,282 @@
+//! Draining events through priority lanes, without starving the low priorities.
+
+use budget::MemoryBudget;
+use queue::BoundedQueue;
+pub use queue::DEFAULT_CAPACITY;
+use {ControlFlow, DropSink, EventsLoop, EventsLoopProxy, OverflowPolicy};
+
+// The buffers of the lanes, and the position of the weighted round-robin.
+struct Lanes<Event> {
+    queues: Vec<BoundedQueue<Event>>,
+    weights: Vec<usize>,
+    lane: usize,
+    credit: usize,
+}
+
+impl<Event> Lanes<Event> {
+    fn push(&mut self, lane: usize, event: Event) {
+        let last = self.queues.len() - 1;
+        self.queues[lane.min(last)].push(event);
+    }
+
+    fn len(&self) -> usize {
+        self.queues.iter().map(BoundedQueue::len).sum()
+    }
+
+    // Any lane can receive the next event, so the fullest one bounds the headroom.
+    fn headroom(&self) -> usize {
+        self.queues
+            .iter()
+            .map(BoundedQueue::headroom)
+            .min()
+            .unwrap_or(0)
+    }
+
+    // Takes the next event in the round-robin order.
+    fn pop(&mut self) -> Option<Event> {
+        if self.queues.iter().all(BoundedQueue::is_empty) {
+            // Start the next burst from the highest priority.
+            self.lane = 0;
+            self.credit = self.weights[0];
+            return None;
+        }
+        loop {
+            if self.credit > 0 {
+                if let Some(event) = self.queues[self.lane].pop() {
+                    self.credit -= 1;
+                    return Some(event);
+                }
+            }
+            self.lane = (self.lane + 1) % self.queues.len();
+            self.credit = self.weights[self.lane];
+        }
+    }
+}
+
+/// Wraps an `EventsLoop`, sorts its events into priority lanes, and drains the lanes from the
+/// highest priority to the lowest, in a weighted round-robin.
+///
+/// The lanes are numbered from 0, the highest priority, and the classifier given to `new` picks
+/// the lane of every event; lanes past the last one fall into the last one. Each cycle of the
+/// round-robin delivers up to `weights[0]` events of lane 0, then up to `weights[1]` events of
+/// lane 1, and so on, skipping the lanes that are empty. A lane thus gets at least its share of
+/// every cycle however busy the lanes above it are, so that the low priorities are never starved.
+/// With weights of `[4, 2, 1]`, for example, a cycle delivers four high, two medium and one low
+/// priority events while every lane is busy. Weights of zero count as one.
+///
+/// Within a lane, the events keep their order. Every lane is bounded, and the `OverflowPolicy`
+/// decides which events are discarded once it is full.
+///
+/// `poll_events` sorts the pending events of the inner loop and delivers them all. `run` takes
+/// the pending events of the inner loop before every delivery, so that a new high priority event
+/// goes ahead of the buffered low priority ones as soon as the round-robin allows. If the
+/// callback of `run` exits, the buffered events are kept for the next call.
+pub struct LanedLoop<'a, L, Event> {
+    inner: L,
+    classify: Box<dyn FnMut(&Event) -> usize + 'a>,
+    lanes: Lanes<Event>,
+}
+
+impl<'a, L, Event> LanedLoop<'a, L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    /// Builds the adapter around `inner`, with one lane per weight, sorting every event into the
+    /// lane returned by `classify`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weights` is empty.
+    pub fn new<F>(inner: L, weights: &[usize], classify: F) -> LanedLoop<'a, L, Event>
+    where
+        F: FnMut(&Event) -> usize + 'a,
+    {
+        assert!(!weights.is_empty(), "a LanedLoop needs at least one lane");
+        let weights: Vec<usize> = weights.iter().map(|&weight| weight.max(1)).collect();
+        LanedLoop {
+            inner,
+            classify: Box::new(classify),
+            lanes: Lanes {
+                queues: weights
+                    .iter()
+                    .map(|_| BoundedQueue::new(DEFAULT_CAPACITY, OverflowPolicy::DropOldest))
+                    .collect(),
+                lane: 0,
+                credit: weights[0],
+                weights,
+            },
+        }
+    }
+
+    /// Bounds every lane to `capacity` events, applying `policy` when it is full.
+    pub fn with_capacity(
+        mut self,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> LanedLoop<'a, L, Event> {
+        for queue in &mut self.lanes.queues {
+            queue.set_bounds(capacity, policy);
+        }
+        self
+    }
+
+    /// Reports the events discarded from any lane to `sink`, see `DropSink`.
+    pub fn with_drop_sink(mut self, sink: DropSink<Event>) -> LanedLoop<'a, L, Event> {
+        for queue in &mut self.lanes.queues {
+            queue.set_drop_sink(sink.clone());
+        }
+        self
+    }
+
+    /// Draws the memory of every lane from `budget`, see `MemoryBudget`.
+    pub fn with_memory_budget(mut self, budget: MemoryBudget<Event>) -> LanedLoop<'a, L, Event> {
+        for queue in &mut self.lanes.queues {
+            queue.set_budget(budget.clone());
+        }
+        self
+    }
+
+    /// Returns the number of events buffered in `lane`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such lane.
+    pub fn lane_len(&self, lane: usize) -> usize {
+        self.lanes.queues[lane].len()
+    }
+
+    /// Returns the wrapped loop. The buffered events are dropped.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+impl<'a, L, Event> EventsLoop<Event> for LanedLoop<'a, L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let LanedLoop {
+            ref mut inner,
+            ref mut classify,
+            ref mut lanes,
+        } = *self;
+        inner.poll_events(&mut |event| lanes.push(classify(&event), event));
+        while let Some(event) = lanes.pop() {
+            callback(event);
+        }
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let LanedLoop {
+            ref mut inner,
+            ref mut classify,
+            ref mut lanes,
+        } = *self;
+        loop {
+            inner.poll_events(&mut |event| lanes.push(classify(&event), event));
+            if let Some(event) = lanes.pop() {
+                if callback(event).is_exit() {
+                    return;
+                }
+                continue;
+            }
+
+            let mut received = false;
+            inner.run(&mut |event| {
+                lanes.push(classify(&event), event);
+                received = true;
+                ControlFlow::Break
+            });
+            if !received {
+                return;
+            }
+        }
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    /// Returns the headroom of the fullest lane, or that of the wrapped loop if it is smaller.
+    fn remaining_capacity(&self) -> Option<usize> {
+        let own = self.lanes.headroom();
+        Some(
+            self.inner
+                .remaining_capacity()
+                .map_or(own, |inner| inner.min(own)),
+        )
+    }
+
+    /// Returns `true` once the inner loop is closed and every lane is empty.
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed() && self.lanes.len() == 0
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    /// Counts the buffered events along with those pending in the inner loop.
+    fn pending_count(&self) -> Option<usize> {
+        self.inner
+            .pending_count()
+            .map(|pending| pending + self.lanes.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LanedLoop;
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop};
+
+    // Events are named after their lane: 'h' for 0, 'm' for 1 and 'l' for 2.
+    fn lane(event: &char) -> usize {
+        match *event {
+            'h' => 0,
+            'm' => 1,
+            _ => 2,
+        }
+    }
+
+    #[test]
+    fn lanes_are_drained_by_weight() {
+        let mut events = vec!['l', 'l', 'm', 'm', 'm'];
+        events.extend(vec!['h'; 9]);
+        let mut l = LanedLoop::new(MockEventsLoop::new(events), &[3, 2, 1], lane);
+
+        let mut delivered = String::new();
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered, "hhhmmlhhhmlhhh");
+    }
+
+    #[test]
+    fn low_priority_gets_through_a_flood() {
+        let mut events = vec!['l'];
+        events.extend(vec!['h'; 100]);
+        let mut l = LanedLoop::new(MockEventsLoop::new(events), &[4, 1], lane);
+
+        let mut position = 0;
+        l.run(&mut |e| {
+            if e == 'l' {
+                return ControlFlow::Break;
+            }
+            position += 1;
+            ControlFlow::Continue
+        });
+        assert_eq!(position, 4);
+        assert_eq!(l.lane_len(0), 96);
+
+        let mut rest = 0;
+        l.run(&mut |_| {
+            rest += 1;
+            ControlFlow::Continue
+        });
+        assert_eq!(rest, 96);
+    }
+}
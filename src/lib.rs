@@ -1,4 +1,63 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
 
+use clock::{Clock, IdleBackoff, SystemClock};
+use coop::{DEFAULT_IDLE_INTERVAL, DEFAULT_MAX_IDLE_INTERVAL};
+use inject::Injector;
+use swap::CallbackCell;
+use wakeup::OnWakeup;
+
+pub mod bench;
+pub mod bounded;
+pub mod budget;
+pub mod channel;
+pub mod clock;
+pub mod coalesce;
+pub mod command;
+pub mod coop;
+pub mod dedup;
+pub mod failover;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "profiling")]
+pub mod flame;
+pub mod fns;
+pub mod gate;
+pub mod heartbeat;
+pub mod inject;
+pub mod laned;
+pub mod lifecycle;
+pub mod merge;
+pub mod metrics;
+pub mod migrate;
+pub mod offload;
+pub mod pace;
+pub mod partition;
+pub mod pipeline;
+pub mod registry;
+pub mod reorder;
+pub mod replay;
+pub mod routing;
+pub mod simulated;
+pub mod single;
+pub mod slow;
+pub mod split;
+pub mod sse;
+pub mod stateful;
+pub mod swap;
+pub mod transition;
+pub mod wakeup;
+
+mod closed;
+mod queue;
+
+#[cfg(test)]
+mod mock;
 
 /// Returned by the user callback given to the `EventsLoop::run` method.
 ///
@@ -7,8 +66,190 @@
 pub enum ControlFlow {
     /// Continue looping and waiting for events.
     Continue,
+    /// Continue looping, but when no events are pending, don't wait for new ones past the given
+    /// instant.
+    ///
+    /// Loops that can't wait with a timeout treat this the same as `Continue`.
+    WaitUntil(Instant),
     /// Break from the event loop.
     Break,
+    /// Break from the event loop, reporting the given exit code.
+    ///
+    /// Loops treat this the same as `Break`. The code is returned by `EventsLoop::try_run`.
+    ExitWithCode(i32),
+}
+
+impl ControlFlow {
+    /// Returns `true` for the variants that make `run` return, `Break` and `ExitWithCode`.
+    pub fn is_exit(&self) -> bool {
+        matches!(*self, ControlFlow::Break | ControlFlow::ExitWithCode(_))
+    }
+
+    /// Combines two decisions into the stricter one: breaks if either breaks.
+    ///
+    /// The variants rank from the most lenient to the strictest as `Continue`, `WaitUntil`,
+    /// `Break`, and `ExitWithCode`, which wins over `Break` so that the code isn't lost. Of two
+    /// `WaitUntil`, the earlier deadline wins. Of two `ExitWithCode`, the code of `self` wins.
+    pub fn and(self, other: ControlFlow) -> ControlFlow {
+        match (self, other) {
+            (ControlFlow::WaitUntil(a), ControlFlow::WaitUntil(b)) => {
+                ControlFlow::WaitUntil(a.min(b))
+            }
+            _ if other.rank() > self.rank() => other,
+            _ => self,
+        }
+    }
+
+    /// Combines two decisions into the more lenient one: continues if either continues.
+    ///
+    /// This ranks the variants as `and` does. Of two `WaitUntil`, the later deadline wins. Of two
+    /// `ExitWithCode`, the code of `self` wins.
+    pub fn or(self, other: ControlFlow) -> ControlFlow {
+        match (self, other) {
+            (ControlFlow::WaitUntil(a), ControlFlow::WaitUntil(b)) => {
+                ControlFlow::WaitUntil(a.max(b))
+            }
+            _ if other.rank() < self.rank() => other,
+            _ => self,
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match *self {
+            ControlFlow::Continue => 0,
+            ControlFlow::WaitUntil(_) => 1,
+            ControlFlow::Break => 2,
+            ControlFlow::ExitWithCode(_) => 3,
+        }
+    }
+}
+
+/// Combines the decisions with `ControlFlow::and`, so that the result breaks if any of them
+/// does. No decision at all makes `Continue`.
+///
+/// This lets a dispatcher that fans an event out to several handlers reconcile their results
+/// with `collect`.
+impl FromIterator<ControlFlow> for ControlFlow {
+    fn from_iter<I: IntoIterator<Item = ControlFlow>>(flows: I) -> ControlFlow {
+        flows
+            .into_iter()
+            .fold(ControlFlow::Continue, ControlFlow::and)
+    }
+}
+
+/// What a bounded buffer does with a new event when it is already full.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    /// Discard the oldest buffered event to make room for the new one.
+    DropOldest,
+    /// Discard the new event and keep the buffer as it is.
+    DropNewest,
+    /// Refuse the new event and hand it back to the producer with an error.
+    ///
+    /// Adapters that have no producer to report to discard the new event, as with `DropNewest`.
+    Reject,
+}
+
+impl OverflowPolicy {
+    /// Pushes `event` at the back of `buffer`, applying the policy if it already holds `capacity`
+    /// events. Returns the event that was discarded, if any.
+    pub(crate) fn push<T>(self, buffer: &mut VecDeque<T>, capacity: usize, event: T) -> Option<T> {
+        if buffer.len() < capacity {
+            buffer.push_back(event);
+            return None;
+        }
+        match self {
+            OverflowPolicy::DropOldest if capacity > 0 => {
+                let dropped = buffer.pop_front();
+                buffer.push_back(event);
+                dropped
+            }
+            _ => Some(event),
+        }
+    }
+}
+
+/// What `EventsLoop::poll_events_with_feedback` reports about the buffer of the loop.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BufferStatus {
+    /// The number of events delivered by the call.
+    pub drained: usize,
+    /// The number of events the buffer can take before it is full, or `None` if the backend is
+    /// unbounded or can't tell.
+    pub remaining_capacity: Option<usize>,
+}
+
+/// An observer notified of every event that an adapter discards, for example because its buffer
+/// is full.
+///
+/// The adapters that discard events take it with `with_drop_sink`, and report to it every event
+/// that their `OverflowPolicy` or their `budget::MemoryBudget` leaves no room for. Without one,
+/// the discarded events are dropped silently.
+///
+/// The same observer can be given to several adapters, in order to count or log all the drops of
+/// a pipeline in one place. It runs on the thread of the `EventsLoop`, in the middle of event
+/// delivery, so it must be cheap.
+pub type DropSink<Event> = Rc<RefCell<dyn FnMut(Event)>>;
+
+/// A zero-sized marker that makes the type embedding it neither `Send` nor `Sync`.
+///
+/// An `EventsLoop` must stay on the thread that created it, see the docs of the trait. Backends
+/// whose fields happen to be thread-safe can embed this marker to opt out of `Send` and `Sync`,
+/// so that moving the loop to another thread is a compile error rather than a latent bug. The
+/// channel and bounded loops of this crate embed it, the simulated one is already confined by
+/// its `Rc`s, and their proxies remain `Send`:
+///
+/// ```
+/// use events_loop::bounded::BoundedProxy;
+/// use events_loop::channel::ChannelProxy;
+///
+/// fn assert_send<T: Send>() {}
+/// assert_send::<ChannelProxy<u32>>();
+/// assert_send::<BoundedProxy<u32>>();
+/// assert_send::<Box<dyn events_loop::EventsLoopProxy>>();
+/// ```
+///
+/// Whereas the loops themselves can't be sent:
+///
+/// ```compile_fail,E0277
+/// fn assert_send<T: Send>() {}
+/// assert_send::<events_loop::channel::ChannelEventsLoop<u32>>();
+/// ```
+///
+/// ```compile_fail,E0277
+/// fn assert_send<T: Send>() {}
+/// assert_send::<events_loop::bounded::BoundedEventsLoop<u32>>();
+/// ```
+///
+/// ```compile_fail,E0277
+/// fn assert_send<T: Send>() {}
+/// assert_send::<events_loop::simulated::SimulatedLoop<u32>>();
+/// ```
+///
+/// Nor shared:
+///
+/// ```compile_fail,E0277
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<events_loop::channel::ChannelEventsLoop<u32>>();
+/// ```
+///
+/// ```compile_fail,E0277
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<events_loop::bounded::BoundedEventsLoop<u32>>();
+/// ```
+///
+/// ```compile_fail,E0277
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<events_loop::simulated::SimulatedLoop<u32>>();
+/// ```
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NotSendSync(PhantomData<*const ()>);
+
+impl NotSendSync {
+    /// Builds the marker.
+    pub fn new() -> NotSendSync {
+        NotSendSync(PhantomData)
+    }
 }
 
 /// Provides a way to retrieve events from the system and from the windows that were registered to
@@ -29,9 +270,77 @@ pub trait EventsLoop<Event> {
     /// and returns.
     fn poll_events(&mut self, callback: &mut dyn FnMut(Event));
 
+    /// Repeatedly calls `poll_events` until no events are left, or until `deadline` has passed.
+    ///
+    /// Unlike a single call to `poll_events`, this also delivers the events that arrived while the
+    /// previous ones were being processed, while bounding the time spent doing so. This helps a
+    /// loop whose callbacks are slow keep up with its queue, without starving the rest of the
+    /// iteration. The deadline is checked between calls to `poll_events`, so it can be exceeded
+    /// by the time it takes to deliver one batch.
+    ///
+    /// Returns `true` if the queue was emptied, and `false` if the deadline passed first.
+    fn drain_until_deadline(&mut self, deadline: Instant, callback: &mut dyn FnMut(Event)) -> bool {
+        self.drain_until_deadline_with_clock(&SystemClock, deadline, callback)
+    }
+
+    /// Same as `drain_until_deadline`, reading the time from `clock`.
+    fn drain_until_deadline_with_clock(
+        &mut self,
+        clock: &dyn Clock,
+        deadline: Instant,
+        callback: &mut dyn FnMut(Event),
+    ) -> bool {
+        while clock.now() < deadline {
+            let mut delivered = false;
+            self.poll_events(&mut |event| {
+                delivered = true;
+                callback(event);
+            });
+            if !delivered {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Same as `poll_events`, except that when no events are pending, the loop lets the host
+    /// wait for them by calling `wait`.
+    ///
+    /// This is meant for hosts that own the blocking, such as a reactor with its own wait
+    /// primitive. `wait` receives the longest time the loop can afford to wait, or `None` if it
+    /// can wait forever, and returns whether an event may have become available, in which case
+    /// the loop polls again. If it returns `false`, `poll_events_with_waiter` returns without
+    /// delivering anything.
+    ///
+    /// Most backends can't hand their wait out, because it happens in a system call or on a
+    /// primitive they don't expose. Those keep the default implementation, which never calls
+    /// `wait` and falls back to blocking in `run` until an event arrives, then delivers it along
+    /// with the other pending ones.
+    fn poll_events_with_waiter(
+        &mut self,
+        _wait: &mut dyn FnMut(Option<Duration>) -> bool,
+        callback: &mut dyn FnMut(Event),
+    ) {
+        let mut delivered = false;
+        self.poll_events(&mut |event| {
+            delivered = true;
+            callback(event);
+        });
+        if delivered {
+            return;
+        }
+        let received = wait_for_event(self, &mut |event| {
+            callback(event);
+            ControlFlow::Continue
+        });
+        if received.is_some() {
+            self.poll_events(callback);
+        }
+    }
+
     /// Calls `callback` every time an event is received. If no event is available, sleeps the
-    /// current thread and waits for an event. If the callback returns `ControlFlow::Break` then
-    /// `run` will immediately return.
+    /// current thread and waits for an event. If the callback returns `ControlFlow::Break` or
+    /// `ControlFlow::ExitWithCode` then `run` will immediately return.
     ///
     /// # Danger!
     ///
@@ -42,7 +351,567 @@ pub trait EventsLoop<Event> {
     /// Creates an `EventsLoopProxy` that can be used to wake up the `EventsLoop` from another
     /// thread.
     fn create_proxy(&self) -> Box<dyn EventsLoopProxy>;
+
+    /// Same as `create_proxy`, except that the proxy doesn't count as a producer of the loop: a
+    /// loop that closes once its proxies are gone, see `is_closed`, still closes while it exists,
+    /// and its wakeups fail while no other proxy is left. This suits the adapters that only need to wake a loop up, such
+    /// as `failover::Failover`, without keeping it open. The default implementation returns
+    /// `create_proxy`.
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.create_proxy()
+    }
+
+    /// Returns the number of events waiting to be delivered, or `None` if the backend can't
+    /// tell.
+    ///
+    /// The count is a snapshot: events can arrive from other threads right after it is taken.
+    /// Adapters that buffer events count them along with those of the loop they wrap. Adapters
+    /// that filter or expand events return the count of the loop they wrap as is.
+    fn pending_count(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the number of events the loop can still buffer before it is full, or `None` if
+    /// it is unbounded or can't tell.
+    ///
+    /// Like `pending_count`, this is a snapshot. Adapters with a bounded buffer of their own
+    /// report its headroom, or that of the loop they wrap if it is smaller; the others report
+    /// that of the loop they wrap.
+    fn remaining_capacity(&self) -> Option<usize> {
+        None
+    }
+
+    /// Same as `poll_events`, and then reports how many events were delivered and how much room
+    /// is left in the buffer of the loop, see `remaining_capacity`.
+    ///
+    /// This lets a consumer tell its producers to slow down when the headroom shrinks, or to
+    /// speed up when it drains faster than they fill it.
+    fn poll_events_with_feedback(&mut self, callback: &mut dyn FnMut(Event)) -> BufferStatus {
+        let mut drained = 0;
+        self.poll_events(&mut |event| {
+            drained += 1;
+            callback(event);
+        });
+        BufferStatus {
+            drained,
+            remaining_capacity: self.remaining_capacity(),
+        }
+    }
+
+    /// Returns `true` if the `EventsLoop` will never produce an event again, for example because
+    /// every producer it was fed by is gone.
+    ///
+    /// This lets the adapters that combine several loops stop polling the ones that are done.
+    /// Backends that can't tell always return `false`. Adapters report the state of the loop
+    /// they wrap, and those that buffer events wait for their buffer to be empty as well.
+    fn is_closed(&self) -> bool {
+        false
+    }
+
+    /// Returns `true` while `run` or `poll_events` is executing.
+    ///
+    /// Library code can check this to assert that it is called from the right context, and
+    /// panic with a clear message instead of attempting a reentrant call. The loop is confined
+    /// to its thread, so backends can track this in a plain `Cell<bool>`, without any thread
+    /// local. The default implementation always returns `false`. The loops of this crate track
+    /// it, and the adapters report the state of the loop they wrap, so an adapter that delivers
+    /// buffered events on its own returns `false` while doing so.
+    fn is_running(&self) -> bool {
+        false
+    }
+
+    /// Same as `run`, except that the callback also receives an `Injector`.
+    ///
+    /// Events pushed into the injector are delivered to the callback as soon as it returns,
+    /// before the loop takes the next event from the system or blocks again. This lets the
+    /// handling of an event synchronously trigger derived events (for example a command that
+    /// requests a redraw) without a round-trip through the backend. The injected events are
+    /// delivered in the order they were pushed. If the callback breaks, the injected events that
+    /// haven't been delivered yet are dropped.
+    fn run_with_injector(
+        &mut self,
+        callback: &mut dyn FnMut(Event, &Injector<Event>) -> ControlFlow,
+    ) {
+        let injector = Injector::new();
+        self.run(&mut |event| {
+            let mut flow = callback(event, &injector);
+            while !flow.is_exit() {
+                match injector.pop() {
+                    Some(event) => flow = callback(event, &injector),
+                    None => break,
+                }
+            }
+            flow
+        });
+    }
+
+    /// Same as `run`, except that the events matching `predicate` are first shown to `on_break`.
+    ///
+    /// This is a breakpoint for debugging: `on_break` runs synchronously with the matching event,
+    /// before the callback does, so it can print the state of the application or be the place
+    /// where a debugger stops. The loop then carries on normally, with the event delivered to the
+    /// callback as usual.
+    fn run_with_breakpoint<F>(
+        &mut self,
+        mut predicate: F,
+        on_break: &mut dyn FnMut(&Event),
+        callback: &mut dyn FnMut(Event) -> ControlFlow,
+    ) where
+        Self: Sized,
+        F: FnMut(&Event) -> bool,
+    {
+        self.run(&mut |event| {
+            if predicate(&event) {
+                on_break(&event);
+            }
+            callback(event)
+        });
+    }
+
+    /// Same as `run`, delivering the events to the callback held by `cell`.
+    ///
+    /// The callback can be replaced while the loop runs through the `SwapHandle`s of the cell,
+    /// see `CallbackCell`. This avoids breaking out of `run` and entering it again just to change
+    /// the handler.
+    fn run_swappable(&mut self, cell: &mut CallbackCell<Event>) {
+        self.run(&mut |event| cell.call(event));
+    }
+
+    /// Same as `run`, except that `hook` is called once per iteration of the loop, after the
+    /// pending events have been drained and before waiting for new ones.
+    ///
+    /// This is meant to let a single-threaded executor make progress alongside the loop, for
+    /// example by calling `run_until_stalled` on a futures `LocalPool` from the hook. If the
+    /// executor has timers pending, have the callback return `ControlFlow::WaitUntil` with the
+    /// earliest one, so that the loop wakes up and runs the hook in time instead of waiting for
+    /// the next event. Once the deadline has passed, the loop waits for events indefinitely again.
+    ///
+    /// This is built on top of `poll_events` and `run`, which can't wait with a timeout. While
+    /// `pending_count` reports pending events, they are delivered one at a time through `run`,
+    /// so that none is lost when the callback exits; a backend that can't tell its count is
+    /// drained with `poll_events` instead, and the events that follow a break are then
+    /// discarded. Until a `WaitUntil` deadline, the loop sleeps in slices, which grow from
+    /// `coop::DEFAULT_IDLE_INTERVAL` up to `coop::DEFAULT_MAX_IDLE_INTERVAL` while nothing
+    /// arrives, and looks for new events and runs the hook between them.
+    fn run_with_poll_hook(
+        &mut self,
+        hook: &mut dyn FnMut(),
+        callback: &mut dyn FnMut(Event) -> ControlFlow,
+    ) {
+        let mut flow = ControlFlow::Continue;
+        let mut idle = IdleBackoff::new(DEFAULT_IDLE_INTERVAL, DEFAULT_MAX_IDLE_INTERVAL);
+        loop {
+            if drain_one_by_one(self, callback, &mut flow) {
+                idle.reset();
+            }
+            if flow.is_exit() {
+                return;
+            }
+            hook();
+
+            if !wait_for_iteration(self, callback, &mut flow, &mut idle) {
+                return;
+            }
+        }
+    }
+
+    /// Same as `run`, except that `on_backlog` is called with the number of pending events
+    /// whenever it exceeds `threshold` at the start of an iteration of the loop.
+    ///
+    /// A backlog that keeps growing means that the callback doesn't keep up with the events, which
+    /// `on_backlog` can log or react to by shedding load. The backlog is read with
+    /// `pending_count` before the pending events are drained. On backends that don't support
+    /// `pending_count`, `on_backlog` is never called and this behaves like `run`.
+    ///
+    /// As with `run_with_poll_hook`, the events that follow a break stay pending on backends that
+    /// support `pending_count`, and the loop sleeps in growing slices until a `WaitUntil`
+    /// deadline, looking for new events between them.
+    fn run_with_backlog_monitor(
+        &mut self,
+        threshold: usize,
+        on_backlog: &mut dyn FnMut(usize),
+        callback: &mut dyn FnMut(Event) -> ControlFlow,
+    ) {
+        let mut flow = ControlFlow::Continue;
+        let mut idle = IdleBackoff::new(DEFAULT_IDLE_INTERVAL, DEFAULT_MAX_IDLE_INTERVAL);
+        loop {
+            match self.pending_count() {
+                Some(pending) if pending > threshold => on_backlog(pending),
+                _ => {}
+            }
+            if drain_one_by_one(self, callback, &mut flow) {
+                idle.reset();
+            }
+            if flow.is_exit() {
+                return;
+            }
+
+            if !wait_for_iteration(self, callback, &mut flow, &mut idle) {
+                return;
+            }
+        }
+    }
+
+    /// Runs the loop frame by frame, at `target_fps` frames per second.
+    ///
+    /// Each frame, the pending events are delivered to `event_callback`, then `frame_callback`
+    /// is called with the time elapsed since the previous frame (zero for the first one), and
+    /// the loop sleeps for the rest of the frame interval. The events that arrive while the loop
+    /// sleeps are delivered at the start of the next frame. If a frame overruns its interval,
+    /// the next one starts right away and the schedule restarts from there, so the debt doesn't
+    /// accumulate into a burst of frames. `run_framed` returns as soon as either callback
+    /// breaks.
+    fn run_framed(
+        &mut self,
+        target_fps: u32,
+        event_callback: &mut dyn FnMut(Event) -> ControlFlow,
+        frame_callback: &mut dyn FnMut(Duration) -> ControlFlow,
+    ) {
+        self.run_framed_with_clock(&SystemClock, target_fps, event_callback, frame_callback)
+    }
+
+    /// Same as `run_framed`, reading the time from and sleeping on `clock`.
+    fn run_framed_with_clock(
+        &mut self,
+        clock: &dyn Clock,
+        target_fps: u32,
+        event_callback: &mut dyn FnMut(Event) -> ControlFlow,
+        frame_callback: &mut dyn FnMut(Duration) -> ControlFlow,
+    ) {
+        let interval = Duration::from_secs(1) / target_fps.max(1);
+        let mut last_frame = clock.now();
+        let mut next_frame = last_frame;
+        loop {
+            let mut flow = ControlFlow::Continue;
+            drain(self, event_callback, &mut flow);
+            if flow.is_exit() {
+                return;
+            }
+
+            let now = clock.now();
+            let delta = now - last_frame;
+            last_frame = now;
+            if frame_callback(delta).is_exit() {
+                return;
+            }
+
+            next_frame += interval;
+            let now = clock.now();
+            if next_frame < now {
+                next_frame = now;
+            }
+            clock.sleep_until(next_frame);
+        }
+    }
+
+    /// Runs the loop in fixed time steps of length `tick`, delivering the events of every step
+    /// as one batch.
+    ///
+    /// The steps are numbered from 0, starting when `run_ticked` is called. At the end of every
+    /// step, as if a `ControlFlow::WaitUntil` deadline had expired on the boundary, the pending
+    /// events are drained and `on_tick` is called with them and the index of the step. A step
+    /// without events still fires, with an empty slice. If a call to `on_tick` overruns the
+    /// following steps, they fire back to back, empty except for the last one, which receives
+    /// the events drained in the meantime; the indices thus keep matching the elapsed time.
+    /// `run_ticked` returns as soon as `on_tick` breaks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tick` is zero.
+    fn run_ticked(
+        &mut self,
+        tick: Duration,
+        on_tick: &mut dyn FnMut(&[Event], u64) -> ControlFlow,
+    ) {
+        self.run_ticked_with_clock(&SystemClock, tick, on_tick)
+    }
+
+    /// Same as `run_ticked`, reading the time from and sleeping on `clock`.
+    fn run_ticked_with_clock(
+        &mut self,
+        clock: &dyn Clock,
+        tick: Duration,
+        on_tick: &mut dyn FnMut(&[Event], u64) -> ControlFlow,
+    ) {
+        assert!(
+            tick > Duration::from_secs(0),
+            "the tick of run_ticked can't be zero"
+        );
+        let mut index = 0;
+        let mut boundary = clock.now() + tick;
+        loop {
+            clock.sleep_until(boundary);
+            let mut batch = Vec::new();
+            self.poll_events(&mut |event| batch.push(event));
+
+            let now = clock.now();
+            while boundary + tick <= now {
+                if on_tick(&[], index).is_exit() {
+                    return;
+                }
+                index += 1;
+                boundary += tick;
+            }
+            if on_tick(&batch, index).is_exit() {
+                return;
+            }
+            index += 1;
+            boundary += tick;
+        }
+    }
+
+    /// Runs the loop in step with the display, presenting a frame before every vsync.
+    ///
+    /// Each frame starts by calling `next_vsync` for the instant of the upcoming vsync. The
+    /// pending events are then drained to `event_callback`, as with `drain_until_deadline`, but
+    /// not past the vsync, after which `present_callback` is called, for example to predict the
+    /// input up to the vsync and render. The loop then sleeps until the vsync, as a
+    /// `ControlFlow::WaitUntil` deadline would, and the events that arrive in the meantime are
+    /// delivered in the next frame. Since the presentation never happens in the event callback,
+    /// a blocking swap in it doesn't delay the input. `run_with_present` returns as soon as
+    /// either callback breaks.
+    fn run_with_present(
+        &mut self,
+        next_vsync: &mut dyn FnMut() -> Instant,
+        event_callback: &mut dyn FnMut(Event) -> ControlFlow,
+        present_callback: &mut dyn FnMut() -> ControlFlow,
+    ) {
+        self.run_with_present_with_clock(&SystemClock, next_vsync, event_callback, present_callback)
+    }
+
+    /// Same as `run_with_present`, reading the time from and sleeping on `clock`.
+    fn run_with_present_with_clock(
+        &mut self,
+        clock: &dyn Clock,
+        next_vsync: &mut dyn FnMut() -> Instant,
+        event_callback: &mut dyn FnMut(Event) -> ControlFlow,
+        present_callback: &mut dyn FnMut() -> ControlFlow,
+    ) {
+        loop {
+            let vsync = next_vsync();
+            let mut flow = ControlFlow::Continue;
+            while clock.now() < vsync {
+                let mut delivered = false;
+                self.poll_events(&mut |event| {
+                    delivered = true;
+                    if !flow.is_exit() {
+                        flow = event_callback(event);
+                    }
+                });
+                if !delivered || flow.is_exit() {
+                    break;
+                }
+            }
+            if flow.is_exit() || present_callback().is_exit() {
+                return;
+            }
+            clock.sleep_until(vsync);
+        }
+    }
+
+    /// Same as `run`, except that the callback can fail.
+    ///
+    /// As soon as the callback returns an `Err`, the loop stops and `try_run` returns that error.
+    /// Otherwise the returned `ControlFlow` is honored as usual, and `try_run` returns the code
+    /// passed to `ControlFlow::ExitWithCode`, or `0` if the loop was stopped with
+    /// `ControlFlow::Break` or returned on its own. An error exit is thus distinct from a `Break`
+    /// exit, without having to smuggle the error out through a captured variable.
+    fn try_run<E>(
+        &mut self,
+        callback: &mut dyn FnMut(Event) -> Result<ControlFlow, E>,
+    ) -> Result<i32, E>
+    where
+        Self: Sized,
+    {
+        let mut outcome = Ok(0);
+        self.run(&mut |event| match callback(event) {
+            Ok(ControlFlow::ExitWithCode(code)) => {
+                outcome = Ok(code);
+                ControlFlow::ExitWithCode(code)
+            }
+            Ok(flow) => flow,
+            Err(err) => {
+                outcome = Err(err);
+                ControlFlow::Break
+            }
+        });
+        outcome
+    }
+
+    /// Wraps the `EventsLoop` so that the events produced by `EventsLoopProxy::wakeup` are
+    /// replaced with the result of `on_wakeup`.
+    ///
+    /// The wakeup events are recognized with `FromWakeup::is_wakeup`, and the other events pass
+    /// through unchanged. This gives a single place to turn a bare wakeup into an event that
+    /// means something to the application, such as a request to check a work queue.
+    fn on_wakeup<F>(self, on_wakeup: F) -> OnWakeup<Self, F>
+    where
+        Self: Sized,
+        F: FnMut() -> Event,
+        Event: FromWakeup,
+    {
+        OnWakeup::new(self, on_wakeup)
+    }
+
+    /// Turns the `EventsLoop` into a closure that returns the next pending event, or `None` if
+    /// there is none.
+    ///
+    /// The closure hands out the events buffered by the last call to `poll_events`, and polls
+    /// again once they have all been returned. It never blocks, and since it owns the
+    /// `EventsLoop` it can't be sent to another thread either.
+    fn into_poll_fn(self) -> impl FnMut() -> Option<Event>
+    where
+        Self: Sized,
+    {
+        let mut events_loop = self;
+        let mut buffered = VecDeque::new();
+        move || {
+            if buffered.is_empty() {
+                events_loop.poll_events(&mut |event| buffered.push_back(event));
+            }
+            buffered.pop_front()
+        }
+    }
+
+    /// Turns the `EventsLoop` into a `RawLoop` that can be driven from C. See
+    /// `ffi::into_raw_callbacks`, which documents the safety contract.
+    #[cfg(feature = "ffi")]
+    fn into_raw_callbacks(self) -> ffi::RawLoop
+    where
+        Self: Sized + 'static,
+    {
+        ffi::into_raw_callbacks(self)
+    }
 }
+
+// Tracks whether a loop is inside `run` or `poll_events`, for `EventsLoop::is_running`.
+#[derive(Debug, Default)]
+pub(crate) struct RunningFlag(Cell<bool>);
+
+impl RunningFlag {
+    // Marks the loop as running until the returned guard is dropped, even by a panic.
+    pub fn enter(&self) -> RunningGuard<'_> {
+        RunningGuard {
+            flag: &self.0,
+            previous: self.0.replace(true),
+        }
+    }
+
+    pub fn get(&self) -> bool {
+        self.0.get()
+    }
+}
+
+pub(crate) struct RunningGuard<'a> {
+    flag: &'a Cell<bool>,
+    previous: bool,
+}
+
+impl<'a> Drop for RunningGuard<'a> {
+    fn drop(&mut self) {
+        self.flag.set(self.previous);
+    }
+}
+
+// Delivers the pending events to `callback` until it breaks, updating `flow`.
+fn drain<L, Event>(
+    events_loop: &mut L,
+    callback: &mut dyn FnMut(Event) -> ControlFlow,
+    flow: &mut ControlFlow,
+) where
+    L: EventsLoop<Event> + ?Sized,
+{
+    events_loop.poll_events(&mut |event| {
+        if !flow.is_exit() {
+            *flow = callback(event);
+        }
+    });
+}
+
+// Delivers the events pending when it's called to `callback` until it exits, updating `flow`,
+// one at a time through `run`, so that the events that follow an exit stay pending. Falls back
+// to `drain` if the loop can't tell its count. Returns `true` if any event was delivered.
+fn drain_one_by_one<L, Event>(
+    events_loop: &mut L,
+    callback: &mut dyn FnMut(Event) -> ControlFlow,
+    flow: &mut ControlFlow,
+) -> bool
+where
+    L: EventsLoop<Event> + ?Sized,
+{
+    let pending = match events_loop.pending_count() {
+        Some(pending) => pending,
+        None => {
+            let mut delivered = false;
+            drain(
+                events_loop,
+                &mut |event| {
+                    delivered = true;
+                    callback(event)
+                },
+                flow,
+            );
+            return delivered;
+        }
+    };
+    for _ in 0..pending {
+        match wait_for_event(events_loop, callback) {
+            Some(next) => *flow = next,
+            None => break,
+        }
+        if flow.is_exit() {
+            break;
+        }
+    }
+    pending > 0
+}
+
+// Prepares the next iteration of a loop built on `poll_events` and `run`. Until a `WaitUntil`
+// deadline, sleeps for the next slice of `idle`, or until the deadline if that comes first.
+// Otherwise, blocks until the next event and delivers it, updating `flow`. Returns `false` if
+// `run` returned without delivering anything.
+fn wait_for_iteration<L, Event>(
+    events_loop: &mut L,
+    callback: &mut dyn FnMut(Event) -> ControlFlow,
+    flow: &mut ControlFlow,
+    idle: &mut IdleBackoff,
+) -> bool
+where
+    L: EventsLoop<Event> + ?Sized,
+{
+    if let ControlFlow::WaitUntil(deadline) = *flow {
+        let now = SystemClock.now();
+        if now < deadline {
+            SystemClock.sleep_until(deadline.min(now + idle.next()));
+            return true;
+        }
+    }
+    match wait_for_event(events_loop, callback) {
+        Some(next) => {
+            *flow = next;
+            true
+        }
+        None => false,
+    }
+}
+
+// Waits for the next event and delivers it to `callback`. Returns `None` if `run` returned
+// without delivering anything.
+fn wait_for_event<L, Event>(
+    events_loop: &mut L,
+    callback: &mut dyn FnMut(Event) -> ControlFlow,
+) -> Option<ControlFlow>
+where
+    L: EventsLoop<Event> + ?Sized,
+{
+    let mut flow = None;
+    events_loop.run(&mut |event| {
+        flow = Some(callback(event));
+        ControlFlow::Break
+    });
+    flow
+}
+
 /// Used to wake up the `EventsLoop` from another thread.
 pub trait EventsLoopProxy : Send {
     /// Wake up the `EventsLoop` from which this proxy was created.
@@ -53,6 +922,31 @@ pub trait EventsLoopProxy : Send {
     fn wakeup(&self) -> Result<(), EventsLoopClosed>;
 
     fn clone(&self) -> Box<dyn EventsLoopProxy>;
+
+    /// Returns the number of wakeups sent to the `EventsLoop` that it hasn't consumed yet, or
+    /// `None` if this can't be known.
+    ///
+    /// This is a best-effort snapshot, which may be outdated as soon as it is returned. It is
+    /// meant to diagnose producers that wake the loop up far more often than it can keep up with.
+    fn pending_wakeups(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// An `EventsLoopProxy` that can send an event of its own along with the wakeup.
+pub trait EventsLoopPayloadProxy<T>: EventsLoopProxy {
+    /// Wakes up the `EventsLoop` from which this proxy was created, delivering `event` to it.
+    ///
+    /// Returns an `Err` handing the event back if it couldn't be queued.
+    fn wakeup_with(&self, event: T) -> Result<(), WakeupError<T>>;
+
+    /// Returns a `Sender` that feeds the `EventsLoop` directly, if it is backed by a
+    /// `std::sync::mpsc` channel.
+    ///
+    /// Returns `None` for the other loops. Use `channel::bridge` to get a `Sender` regardless.
+    fn as_sender(&self) -> Option<Sender<T>> {
+        None
+    }
 }
 
 impl Clone for Box<dyn EventsLoopProxy> {
@@ -62,6 +956,30 @@ impl Clone for Box<dyn EventsLoopProxy> {
     }    
 }
 
+/// Implemented by event types that can represent the `Awakened` event emitted by a loop when
+/// `EventsLoopProxy::wakeup` is called.
+///
+/// The loops of this crate that carry user events rely on it to implement `EventsLoopProxy`.
+pub trait FromWakeup {
+    /// Returns the event that is delivered when the loop is woken up without a payload.
+    fn from_wakeup() -> Self;
+
+    /// Returns `true` if this event is the one produced by `from_wakeup`.
+    fn is_wakeup(&self) -> bool;
+}
+
+/// Implemented by event types that can represent the application being suspended and resumed,
+/// as mobile platforms do when an application goes to the background and comes back.
+///
+/// `lifecycle::LifecycleProxy` relies on it to signal these transitions to a loop.
+pub trait FromLifecycle {
+    /// Returns the event that is delivered when the application is suspended.
+    fn suspended() -> Self;
+
+    /// Returns the event that is delivered when the application is resumed.
+    fn resumed() -> Self;
+}
+
 /// The error that is returned when an `EventsLoopProxy` attempts to wake up an `EventsLoop` that
 /// no longer exists.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -69,7 +987,7 @@ pub struct EventsLoopClosed;
 
 impl std::fmt::Display for EventsLoopClosed {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.to_string())
+        write!(f, "Tried to wake up a closed `EventsLoop`")
     }
 }
 
@@ -79,6 +997,45 @@ impl std::error::Error for EventsLoopClosed {
     }
 }
 
+/// The error that is returned when an event can't be sent to an `EventsLoop`. The event is
+/// handed back so that the producer can retry or dispose of it.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum WakeupError<T> {
+    /// The `EventsLoop` no longer exists.
+    Closed(T),
+    /// The queue of the `EventsLoop` is full and its overflow policy rejects new events.
+    Full(T),
+}
+
+impl<T> WakeupError<T> {
+    /// Returns the event that couldn't be sent.
+    pub fn into_inner(self) -> T {
+        match self {
+            WakeupError::Closed(event) | WakeupError::Full(event) => event,
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for WakeupError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            WakeupError::Closed(_) => write!(f, "Closed(..)"),
+            WakeupError::Full(_) => write!(f, "Full(..)"),
+        }
+    }
+}
+
+impl<T> std::fmt::Display for WakeupError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            WakeupError::Closed(_) => write!(f, "Tried to send an event to a closed `EventsLoop`"),
+            WakeupError::Full(_) => write!(f, "Tried to send an event to a full `EventsLoop`"),
+        }
+    }
+}
+
+impl<T> std::error::Error for WakeupError<T> {}
+
 
 #[cfg(test)]
 mod tests {
@@ -96,7 +1053,7 @@ mod tests {
         }
 
         fn run(&mut self, callback: &mut dyn FnMut(Events) -> ::ControlFlow){
-                while callback(Events::C) == ::ControlFlow::Continue {
+                while !callback(Events::C).is_exit() {
 
                 }
             }
@@ -114,4 +1071,458 @@ mod tests {
         l.poll_events(&mut |e| println!("{:?}", e));
         l.run(&mut |e| {println!("{:?}", e); ::ControlFlow::Break});
     }
+
+    #[test]
+    fn try_run_stops_on_error() {
+        use ::EventsLoop;
+        let mut l = ::mock::MockEventsLoop::new(vec![1, 2, 3, 4]);
+        let mut delivered = Vec::new();
+        let result = l.try_run(&mut |e| {
+            delivered.push(e);
+            if e == 3 { Err("third event") } else { Ok(::ControlFlow::Continue) }
+        });
+        assert_eq!(result, Err("third event"));
+        assert_eq!(delivered, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn try_run_returns_exit_code() {
+        use ::EventsLoop;
+        let mut l = ::mock::MockEventsLoop::new(vec![1, 2, 3]);
+        let result: Result<i32, ()> = l.try_run(&mut |e| {
+            Ok(if e == 2 { ::ControlFlow::ExitWithCode(7) } else { ::ControlFlow::Continue })
+        });
+        assert_eq!(result, Ok(7));
+    }
+
+    #[test]
+    fn poll_hook_runs_after_each_drain() {
+        use std::time::Duration;
+        use ::EventsLoop;
+        use ::clock::{Clock, MockClock};
+
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut l = ::simulated::SimulatedLoop::new(clock.clone());
+        l.extend((0..3).map(|n| (start + Duration::from_millis(10 * n), n)));
+        l.schedule(start, 10);
+
+        let log = ::std::cell::RefCell::new(Vec::new());
+        l.run_with_poll_hook(&mut || log.borrow_mut().push("hook"), &mut |e| {
+            log.borrow_mut().push(if e == 10 { "ten" } else { "event" });
+            ::ControlFlow::Continue
+        });
+        assert_eq!(
+            log.into_inner(),
+            vec!["event", "ten", "hook", "event", "hook", "event", "hook"]
+        );
+    }
+
+    #[test]
+    fn poll_hook_keeps_the_events_after_a_break() {
+        use bounded::BoundedEventsLoop;
+        use mock::Event;
+        use {EventsLoop, OverflowPolicy};
+
+        let mut l = BoundedEventsLoop::with_capacity(8, OverflowPolicy::Reject);
+        let proxy = l.create_payload_proxy();
+        for n in 0..3 {
+            proxy.wakeup_with(Event::Data(n)).unwrap();
+        }
+
+        let mut seen = Vec::new();
+        l.run_with_poll_hook(&mut || {}, &mut |event| {
+            seen.push(event);
+            if seen.len() == 2 {
+                ::ControlFlow::Break
+            } else {
+                ::ControlFlow::Continue
+            }
+        });
+        assert_eq!(seen, vec![Event::Data(0), Event::Data(1)]);
+        assert_eq!(l.pending_count(), Some(1));
+    }
+
+    #[test]
+    fn poll_hook_wakes_up_before_a_distant_deadline() {
+        use std::thread;
+        use std::time::{Duration, Instant};
+
+        use channel::ChannelEventsLoop;
+        use mock::Event;
+        use {EventsLoop, EventsLoopPayloadProxy};
+
+        let mut l = ChannelEventsLoop::new();
+        let proxy = l.create_payload_proxy();
+        proxy.wakeup_with(Event::Data(0)).unwrap();
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            proxy.wakeup_with(Event::Data(1)).unwrap();
+        });
+
+        let start = Instant::now();
+        let deadline = start + Duration::from_secs(60);
+        let mut hooks = 0;
+        l.run_with_poll_hook(&mut || hooks += 1, &mut |event| match event {
+            Event::Data(1) => ::ControlFlow::Break,
+            _ => ::ControlFlow::WaitUntil(deadline),
+        });
+        sender.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(hooks > 0);
+    }
+
+    #[test]
+    fn backlog_monitor_reports_a_growing_backlog() {
+        use bounded::BoundedEventsLoop;
+        use mock::Event;
+        use {EventsLoop, OverflowPolicy};
+
+        let mut l = BoundedEventsLoop::with_capacity(64, OverflowPolicy::Reject);
+        let proxy = l.create_payload_proxy();
+        proxy.wakeup_with(Event::Data(0)).unwrap();
+
+        // Every event produces two more, so the loop falls further behind at each iteration.
+        let mut backlogs = Vec::new();
+        let mut delivered = 0;
+        l.run_with_backlog_monitor(2, &mut |pending| backlogs.push(pending), &mut |_| {
+            delivered += 1;
+            proxy.wakeup_with(Event::Data(delivered)).unwrap();
+            proxy.wakeup_with(Event::Data(delivered)).unwrap();
+            if delivered == 12 {
+                ::ControlFlow::Break
+            } else {
+                ::ControlFlow::Continue
+            }
+        });
+        assert_eq!(backlogs, vec![3, 7]);
+    }
+
+    #[test]
+    fn backlog_monitor_keeps_the_events_after_a_break() {
+        use bounded::BoundedEventsLoop;
+        use mock::Event;
+        use {EventsLoop, OverflowPolicy};
+
+        let mut l = BoundedEventsLoop::with_capacity(8, OverflowPolicy::Reject);
+        let proxy = l.create_payload_proxy();
+        for n in 0..3 {
+            proxy.wakeup_with(Event::Data(n)).unwrap();
+        }
+
+        let mut backlogs = Vec::new();
+        let mut seen = Vec::new();
+        l.run_with_backlog_monitor(2, &mut |pending| backlogs.push(pending), &mut |event| {
+            seen.push(event);
+            if seen.len() == 2 {
+                ::ControlFlow::Break
+            } else {
+                ::ControlFlow::Continue
+            }
+        });
+        assert_eq!(backlogs, vec![3]);
+        assert_eq!(seen, vec![Event::Data(0), Event::Data(1)]);
+        assert_eq!(l.pending_count(), Some(1));
+    }
+
+    #[test]
+    fn waiter_falls_back_to_blocking_in_run() {
+        use std::thread;
+
+        use channel::ChannelEventsLoop;
+        use mock::Event;
+        use {EventsLoop, EventsLoopPayloadProxy};
+
+        let mut l = ChannelEventsLoop::new();
+        let proxy = l.create_payload_proxy();
+        let producer = thread::spawn(move || {
+            thread::sleep(::std::time::Duration::from_millis(10));
+            proxy.wakeup_with(Event::Data(1)).unwrap();
+        });
+
+        let mut delivered = Vec::new();
+        l.poll_events_with_waiter(
+            &mut |_| panic!("the channel can't hand out its wait"),
+            &mut |e| delivered.push(e),
+        );
+        producer.join().unwrap();
+        assert_eq!(delivered, vec![Event::Data(1)]);
+    }
+
+    #[test]
+    fn breakpoint_fires_before_the_matching_event_is_dispatched() {
+        use std::cell::RefCell;
+
+        use mock::MockEventsLoop;
+        use EventsLoop;
+
+        let log = RefCell::new(Vec::new());
+        MockEventsLoop::new(vec![1, 2, 3, 4]).run_with_breakpoint(
+            |&e| e % 2 == 0,
+            &mut |&e| log.borrow_mut().push(("break", e)),
+            &mut |e| {
+                log.borrow_mut().push(("event", e));
+                ::ControlFlow::Continue
+            },
+        );
+        assert_eq!(
+            log.into_inner(),
+            vec![
+                ("event", 1),
+                ("break", 2),
+                ("event", 2),
+                ("event", 3),
+                ("break", 4),
+                ("event", 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn running_flag_is_restored_when_leaving_the_loop() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        use gate::Gate;
+        use heartbeat::Heartbeat;
+        use simulated::SimulatedLoop;
+        use single::SingleConsumer;
+        use {EventsLoop, RunningFlag};
+
+        let flag = RunningFlag::default();
+        {
+            let _outer = flag.enter();
+            {
+                let _inner = flag.enter();
+                assert!(flag.get());
+            }
+            assert!(flag.get());
+        }
+        assert!(!flag.get());
+
+        let unwound = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _running = flag.enter();
+            panic!("callback failed");
+        }));
+        assert!(unwound.is_err());
+        assert!(!flag.get());
+
+        // The handles share the loop, so one of them can observe it while the other runs it.
+        let mut sim = SimulatedLoop::new(::clock::MockClock::new());
+        sim.inject(());
+        let handle = SingleConsumer::new(sim);
+        let observer = Heartbeat::new(handle.clone());
+        let mut l = Gate::new(handle);
+        let mut seen = Vec::new();
+        assert!(!observer.is_running());
+        l.run(&mut |()| {
+            seen.push(observer.is_running());
+            ::ControlFlow::Continue
+        });
+        assert_eq!(seen, vec![true]);
+        assert!(!observer.is_running());
+        assert!(!l.is_running());
+    }
+
+    #[test]
+    fn drain_stops_at_the_deadline() {
+        use std::time::Duration;
+        use ::EventsLoop;
+        use ::clock::{Clock, MockClock};
+
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut l = ::simulated::SimulatedLoop::new(clock.clone());
+        l.extend((0..100).map(|n| (start + Duration::from_millis(n), n)));
+
+        let mut delivered = Vec::new();
+        let emptied = l.drain_until_deadline_with_clock(&clock, start + Duration::from_millis(5), &mut |e| {
+            delivered.push(e);
+            clock.advance(Duration::from_millis(1));
+        });
+        assert!(!emptied);
+        assert_eq!(delivered, vec![0, 1, 2, 3, 4]);
+
+        let mut l = ::mock::MockEventsLoop::new(vec![1, 2, 3]);
+        assert!(l.drain_until_deadline_with_clock(&clock, clock.now() + Duration::from_millis(1), &mut |_| ()));
+    }
+
+    #[test]
+    fn frames_keep_their_cadence_without_accumulating_debt() {
+        use std::time::Duration;
+        use ::EventsLoop;
+        use ::clock::{Clock, MockClock};
+
+        let ms = Duration::from_millis;
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut l = ::simulated::SimulatedLoop::new(clock.clone());
+        l.extend(vec![(start + ms(50), 1), (start + ms(150), 2)]);
+
+        let log = ::std::cell::RefCell::new(Vec::new());
+        let mut frames = 0;
+        l.run_framed_with_clock(&clock, 10, &mut |e| {
+            log.borrow_mut().push(format!("event {} at {:?}", e, clock.now() - start));
+            ::ControlFlow::Continue
+        }, &mut |delta| {
+            log.borrow_mut().push(format!("frame at {:?} after {:?}", clock.now() - start, delta));
+            frames += 1;
+            if frames == 3 {
+                clock.advance(ms(250));
+            }
+            if frames == 5 { ::ControlFlow::Break } else { ::ControlFlow::Continue }
+        });
+
+        assert_eq!(log.into_inner(), vec![
+            "frame at 0ns after 0ns",
+            "event 1 at 100ms",
+            "frame at 100ms after 100ms",
+            "event 2 at 200ms",
+            "frame at 200ms after 100ms",
+            "frame at 450ms after 250ms",
+            "frame at 550ms after 100ms",
+        ]);
+    }
+
+    #[test]
+    fn control_flows_combine_by_strictness() {
+        use std::time::{Duration, Instant};
+        use ::ControlFlow::*;
+
+        let soon = Instant::now();
+        let later = soon + Duration::from_secs(1);
+        let flows = [Continue, WaitUntil(soon), Break, ExitWithCode(3)];
+        // The expected results of `and`, row by row for `self` in the order of `flows`.
+        let and = [
+            [Continue, WaitUntil(soon), Break, ExitWithCode(3)],
+            [WaitUntil(soon), WaitUntil(soon), Break, ExitWithCode(3)],
+            [Break, Break, Break, ExitWithCode(3)],
+            [ExitWithCode(3), ExitWithCode(3), ExitWithCode(3), ExitWithCode(3)],
+        ];
+        let or = [
+            [Continue, Continue, Continue, Continue],
+            [Continue, WaitUntil(soon), WaitUntil(soon), WaitUntil(soon)],
+            [Continue, WaitUntil(soon), Break, Break],
+            [Continue, WaitUntil(soon), Break, ExitWithCode(3)],
+        ];
+        for (i, &a) in flows.iter().enumerate() {
+            for (j, &b) in flows.iter().enumerate() {
+                assert_eq!(a.and(b), and[i][j], "{:?} and {:?}", a, b);
+                assert_eq!(a.or(b), or[i][j], "{:?} or {:?}", a, b);
+            }
+        }
+
+        assert_eq!(WaitUntil(later).and(WaitUntil(soon)), WaitUntil(soon));
+        assert_eq!(WaitUntil(soon).or(WaitUntil(later)), WaitUntil(later));
+        assert_eq!(ExitWithCode(1).and(ExitWithCode(2)), ExitWithCode(1));
+        assert_eq!(ExitWithCode(1).or(ExitWithCode(2)), ExitWithCode(1));
+    }
+
+    #[test]
+    fn collected_control_flows_break_if_any_does() {
+        use std::iter::FromIterator;
+        use std::time::Instant;
+        use ::ControlFlow;
+        use ::ControlFlow::*;
+
+        let deadline = Instant::now();
+        assert_eq!(ControlFlow::from_iter(vec![]), Continue);
+        assert_eq!(ControlFlow::from_iter(vec![Continue, WaitUntil(deadline)]), WaitUntil(deadline));
+        assert_eq!(vec![Continue, Break, WaitUntil(deadline)].into_iter().collect::<ControlFlow>(), Break);
+        assert_eq!(ControlFlow::from_iter(vec![Break, ExitWithCode(2), Continue]), ExitWithCode(2));
+    }
+
+    #[test]
+    fn ticks_batch_the_events_of_each_step() {
+        use std::time::Duration;
+        use ::EventsLoop;
+        use ::clock::{Clock, MockClock};
+
+        let ms = Duration::from_millis;
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut l = ::simulated::SimulatedLoop::new(clock.clone());
+        l.extend(vec![
+            (start + ms(3), 'a'),
+            (start + ms(7), 'b'),
+            (start + ms(25), 'c'),
+            (start + ms(41), 'd'),
+            (start + ms(62), 'e'),
+        ]);
+
+        let mut ticks = Vec::new();
+        l.run_ticked_with_clock(&clock, ms(10), &mut |batch, index| {
+            ticks.push((index, batch.to_vec(), clock.now() - start));
+            if index == 4 {
+                // Overruns the next two steps.
+                clock.advance(ms(25));
+            }
+            if index == 6 { ::ControlFlow::Break } else { ::ControlFlow::Continue }
+        });
+
+        assert_eq!(ticks, vec![
+            (0, vec!['a', 'b'], ms(10)),
+            (1, vec![], ms(20)),
+            (2, vec!['c'], ms(30)),
+            (3, vec![], ms(40)),
+            (4, vec!['d'], ms(50)),
+            (5, vec![], ms(75)),
+            (6, vec!['e'], ms(75)),
+        ]);
+    }
+
+    #[test]
+    fn present_follows_the_events_drained_before_each_vsync() {
+        use std::cell::RefCell;
+        use std::time::Duration;
+        use ::EventsLoop;
+        use ::clock::{Clock, MockClock};
+
+        let ms = Duration::from_millis;
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut l = ::simulated::SimulatedLoop::new(clock.clone());
+        l.extend(vec![
+            (start + ms(3), 'a'),
+            (start + ms(5), 'b'),
+            (start + ms(20), 'c'),
+            (start + ms(40), 'd'),
+        ]);
+        let scheduler = l.scheduler();
+
+        let delivered = RefCell::new(Vec::new());
+        let mut presents = Vec::new();
+        l.run_with_present_with_clock(
+            &clock,
+            &mut || start + ms(16) * ((clock.now() - start).as_millis() as u32 / 16 + 1),
+            &mut |event| {
+                delivered.borrow_mut().push(event);
+                if event == 'c' {
+                    // Overruns the vsync at 48ms, so the follow-up waits for the next frame.
+                    clock.advance(ms(20));
+                    scheduler.inject('x');
+                }
+                ::ControlFlow::Continue
+            },
+            &mut || {
+                presents.push((clock.now() - start, delivered.borrow().clone()));
+                if presents.len() == 4 { ::ControlFlow::Break } else { ::ControlFlow::Continue }
+            },
+        );
+
+        assert_eq!(presents, vec![
+            (ms(0), vec![]),
+            (ms(16), vec!['a', 'b']),
+            (ms(52), vec!['a', 'b', 'c']),
+            (ms(52), vec!['a', 'b', 'c', 'd', 'x']),
+        ]);
+    }
+
+    #[test]
+    fn poll_fn_returns_buffered_events() {
+        use ::EventsLoop;
+        let mut next = ::mock::MockEventsLoop::new(vec![Events::A, Events::B]).into_poll_fn();
+        assert_eq!(next(), Some(Events::A));
+        assert_eq!(next(), Some(Events::B));
+        assert_eq!(next(), None);
+    }
 }
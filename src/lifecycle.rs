@@ -0,0 +1,152 @@
+//! Signaling the suspension and resumption of the application to a loop.
+
+use std::sync::{Arc, Mutex};
+
+use {EventsLoopPayloadProxy, FromLifecycle, WakeupError};
+
+/// Wraps a payload proxy and sends the lifecycle events of `FromLifecycle` through it, so that
+/// the code reacting to the platform, on any thread, can tell the loop that the application was
+/// suspended or resumed.
+///
+/// The proxy and its clones share the lifecycle state, and drop the signals that don't change
+/// it: suspending an application that is already suspended does nothing, and so does resuming
+/// one that is running. The application starts out running. The loop thus sees the lifecycle
+/// events alternate, starting with a suspend, and every suspend is followed by a resume before
+/// the next suspend. The signals are sent under a lock, so they reach the loop in the order they
+/// changed the state, whatever thread sent them.
+pub struct LifecycleProxy<P> {
+    proxy: P,
+    suspended: Arc<Mutex<bool>>,
+}
+
+impl<P: Clone> Clone for LifecycleProxy<P> {
+    fn clone(&self) -> LifecycleProxy<P> {
+        LifecycleProxy {
+            proxy: self.proxy.clone(),
+            suspended: self.suspended.clone(),
+        }
+    }
+}
+
+impl<P> LifecycleProxy<P> {
+    /// Wraps `proxy`, with the application running.
+    pub fn new(proxy: P) -> LifecycleProxy<P> {
+        LifecycleProxy {
+            proxy,
+            suspended: Arc::new(Mutex::new(false)),
+        }
+    }
+
+    /// Returns `true` if the application is suspended.
+    pub fn is_suspended(&self) -> bool {
+        *self.suspended.lock().unwrap()
+    }
+
+    /// Sends `FromLifecycle::suspended` to the loop, unless the application is already
+    /// suspended.
+    ///
+    /// Returns an `Err` handing the event back if it couldn't be queued, in which case the
+    /// application is still considered running.
+    pub fn signal_suspended<T>(&self) -> Result<(), WakeupError<T>>
+    where
+        P: EventsLoopPayloadProxy<T>,
+        T: FromLifecycle,
+    {
+        self.signal(true, T::suspended)
+    }
+
+    /// Sends `FromLifecycle::resumed` to the loop, unless the application is already running.
+    ///
+    /// Returns an `Err` handing the event back if it couldn't be queued, in which case the
+    /// application is still considered suspended.
+    pub fn signal_resumed<T>(&self) -> Result<(), WakeupError<T>>
+    where
+        P: EventsLoopPayloadProxy<T>,
+        T: FromLifecycle,
+    {
+        self.signal(false, T::resumed)
+    }
+
+    fn signal<T>(&self, suspend: bool, event: fn() -> T) -> Result<(), WakeupError<T>>
+    where
+        P: EventsLoopPayloadProxy<T>,
+    {
+        let mut suspended = self.suspended.lock().unwrap();
+        if *suspended == suspend {
+            return Ok(());
+        }
+        self.proxy.wakeup_with(event())?;
+        *suspended = suspend;
+        Ok(())
+    }
+
+    /// Returns the wrapped proxy.
+    pub fn into_inner(self) -> P {
+        self.proxy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::LifecycleProxy;
+    use channel::ChannelEventsLoop;
+    use {EventsLoop, FromLifecycle, FromWakeup};
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum App {
+        Awakened,
+        Suspended,
+        Resumed,
+    }
+
+    impl FromWakeup for App {
+        fn from_wakeup() -> App {
+            App::Awakened
+        }
+
+        fn is_wakeup(&self) -> bool {
+            *self == App::Awakened
+        }
+    }
+
+    impl FromLifecycle for App {
+        fn suspended() -> App {
+            App::Suspended
+        }
+
+        fn resumed() -> App {
+            App::Resumed
+        }
+    }
+
+    #[test]
+    fn suspends_and_resumes_alternate() {
+        let mut l = ChannelEventsLoop::<App>::new();
+        let lifecycle = LifecycleProxy::new(l.create_payload_proxy());
+
+        lifecycle.signal_resumed().unwrap();
+        lifecycle.signal_suspended().unwrap();
+        lifecycle.signal_suspended().unwrap();
+        assert!(lifecycle.is_suspended());
+        lifecycle.signal_resumed().unwrap();
+
+        let platform = lifecycle.clone();
+        thread::spawn(move || {
+            platform.signal_suspended().unwrap();
+            platform.signal_resumed().unwrap();
+            platform.signal_resumed().unwrap();
+        })
+        .join()
+        .unwrap();
+        assert!(!lifecycle.is_suspended());
+
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(
+            delivered,
+            vec![App::Suspended, App::Resumed, App::Suspended, App::Resumed]
+        );
+    }
+}
@@ -0,0 +1,689 @@
+//! Merging any number of loops of the same type into one.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use clock::{Clock, IdleBackoff, SystemClock};
+pub use coop::{DEFAULT_IDLE_INTERVAL, DEFAULT_MAX_IDLE_INTERVAL};
+use {wait_for_event, ControlFlow, EventsLoop, EventsLoopClosed, EventsLoopProxy};
+
+/// Merges `loops` into a single `EventsLoop`. See `MergeAll`.
+pub fn merge_all<L, Event>(loops: Vec<L>) -> MergeAll<L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    MergeAll {
+        loops: loops
+            .into_iter()
+            .map(|events_loop| Merged {
+                events_loop,
+                buffer: VecDeque::new(),
+            })
+            .collect(),
+        next: 0,
+        idle: IdleBackoff::new(DEFAULT_IDLE_INTERVAL, DEFAULT_MAX_IDLE_INTERVAL),
+    }
+}
+
+// A loop of a `MergeAll`, and the events taken from it that aren't delivered yet.
+struct Merged<L, Event> {
+    events_loop: L,
+    buffer: VecDeque<Event>,
+}
+
+impl<L, Event> Merged<L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    fn is_done(&self) -> bool {
+        self.buffer.is_empty() && self.events_loop.is_closed()
+    }
+
+    // Delivers the buffered events, taking the pending events of the loop first if there are
+    // none. Returns the number of events delivered, and the flow of the last callback.
+    fn drain(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) -> (usize, ControlFlow) {
+        let Merged {
+            ref mut events_loop,
+            ref mut buffer,
+        } = *self;
+        if buffer.is_empty() {
+            events_loop.poll_events(&mut |event| buffer.push_back(event));
+        }
+        let mut count = 0;
+        let mut flow = ControlFlow::Continue;
+        while let Some(event) = buffer.pop_front() {
+            count += 1;
+            flow = callback(event);
+            if flow.is_exit() {
+                break;
+            }
+        }
+        (count, flow)
+    }
+}
+
+/// An `EventsLoop` that delivers the events of a dynamic list of loops of the same type.
+///
+/// Each round polls every loop once, in a round-robin order whose starting point rotates from
+/// one round to the next, so that no loop is systematically served first. The loops that report
+/// themselves closed through `EventsLoop::is_closed`, and whose events have all been delivered,
+/// are removed after the round, and the merged loop is closed once none is left.
+///
+/// If the callback of `run` exits in the middle of a round, the events already taken from the
+/// loop being served stay buffered, and the next call to `poll_events` or `run` resumes the
+/// round with them; `pending_count` counts them.
+///
+/// A loop can't wait on several others at once, so while more than one loop is left, `run` polls
+/// instead of blocking: while every loop is idle, it sleeps between rounds, starting at the idle
+/// interval and doubling with every idle round up to the maximum idle interval, and starts over
+/// once an event is delivered. The maximum bounds both the latency of the first event after a
+/// quiet spell and the number of wakeups of an idle merge. `run` only blocks on
+/// `EventsLoop::run` once a single loop is left, and returns once every loop has been removed.
+///
+/// `create_proxy` returns a proxy that wakes up every loop at once.
+pub struct MergeAll<L, Event> {
+    loops: Vec<Merged<L, Event>>,
+    next: usize,
+    idle: IdleBackoff,
+}
+
+impl<L, Event> MergeAll<L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    /// Sets how long `run` first sleeps once every loop is idle.
+    pub fn with_idle_interval(mut self, interval: Duration) -> MergeAll<L, Event> {
+        self.idle = IdleBackoff::new(interval, self.idle.max);
+        self
+    }
+
+    /// Sets the longest that `run` sleeps between rounds while every loop stays idle.
+    pub fn with_max_idle_interval(mut self, interval: Duration) -> MergeAll<L, Event> {
+        self.idle = IdleBackoff::new(self.idle.min, interval);
+        self
+    }
+
+    /// Returns the number of loops that are still merged.
+    pub fn len(&self) -> usize {
+        self.loops.len()
+    }
+
+    /// Returns `true` if every loop has been removed.
+    pub fn is_empty(&self) -> bool {
+        self.loops.is_empty()
+    }
+
+    /// Returns the loops that are still merged, dropping the events still buffered.
+    pub fn into_inner(self) -> Vec<L> {
+        self.loops.into_iter().map(|l| l.events_loop).collect()
+    }
+
+    // Serves every loop once, starting from the next one in turn, then removes the loops that
+    // are done. Returns the number of events delivered, and the flow of the last callback. If
+    // the callback exits, the next round starts from the loop it stopped in.
+    fn poll_round(
+        &mut self,
+        callback: &mut dyn FnMut(Event) -> ControlFlow,
+    ) -> (usize, ControlFlow) {
+        let count = self.loops.len();
+        let mut delivered = 0;
+        let mut flow = ControlFlow::Continue;
+        for i in 0..count {
+            let index = (self.next + i) % count;
+            let (n, last) = self.loops[index].drain(callback);
+            if n > 0 {
+                delivered += n;
+                flow = last;
+            }
+            if flow.is_exit() {
+                self.next = index;
+                break;
+            }
+        }
+        if count > 0 && !flow.is_exit() {
+            self.next = (self.next + 1) % count;
+        }
+
+        // Keep `next` on the same loop while removing those before it.
+        let removed = self.loops[..self.next]
+            .iter()
+            .filter(|l| l.is_done())
+            .count();
+        self.loops.retain(|l| !l.is_done());
+        self.next -= removed;
+        if self.next >= self.loops.len() {
+            self.next = 0;
+        }
+        (delivered, flow)
+    }
+}
+
+impl<L, Event> EventsLoop<Event> for MergeAll<L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        self.poll_round(&mut |event| {
+            callback(event);
+            ControlFlow::Continue
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let mut flow = ControlFlow::Continue;
+        loop {
+            let (delivered, last) = self.poll_round(callback);
+            if last.is_exit() {
+                return;
+            }
+            if delivered > 0 {
+                flow = last;
+                self.idle.reset();
+                continue;
+            }
+
+            match self.loops.len() {
+                0 => return,
+                1 => {
+                    if let ControlFlow::WaitUntil(deadline) = flow {
+                        SystemClock.sleep_until(deadline);
+                        flow = ControlFlow::Continue;
+                    } else {
+                        match wait_for_event(&mut self.loops[0].events_loop, callback) {
+                            Some(next) => flow = next,
+                            None => return,
+                        }
+                    }
+                }
+                _ => SystemClock.sleep_until(SystemClock.now() + self.idle.next()),
+            }
+            if flow.is_exit() {
+                return;
+            }
+        }
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(BroadcastProxy {
+            proxies: self
+                .loops
+                .iter()
+                .map(|l| l.events_loop.create_proxy())
+                .collect(),
+        })
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(BroadcastProxy {
+            proxies: self
+                .loops
+                .iter()
+                .map(|l| l.events_loop.create_wakeup_proxy())
+                .collect(),
+        })
+    }
+
+    /// Counts the buffered events along with those pending in the loops.
+    fn pending_count(&self) -> Option<usize> {
+        self.loops
+            .iter()
+            .map(|l| {
+                l.events_loop
+                    .pending_count()
+                    .map(|pending| pending + l.buffer.len())
+            })
+            .sum()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.loops.is_empty()
+    }
+
+    fn is_running(&self) -> bool {
+        self.loops.iter().any(|l| l.events_loop.is_running())
+    }
+}
+
+/// Merges `loops` into a single `EventsLoop` that shares the delivery between them in proportion
+/// to their weights. See `WeightedMerge`.
+pub fn merge_weighted<L, Event>(loops: Vec<(L, usize)>) -> WeightedMerge<L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    WeightedMerge {
+        members: loops
+            .into_iter()
+            .map(|(events_loop, weight)| Member {
+                events_loop,
+                weight: weight.max(1),
+                deficit: 0,
+                buffer: VecDeque::new(),
+                delivered: 0,
+            })
+            .collect(),
+        current: 0,
+        credited: false,
+        idle: IdleBackoff::new(DEFAULT_IDLE_INTERVAL, DEFAULT_MAX_IDLE_INTERVAL),
+    }
+}
+
+struct Member<L, Event> {
+    events_loop: L,
+    weight: usize,
+    deficit: usize,
+    buffer: VecDeque<Event>,
+    delivered: u64,
+}
+
+impl<L, Event> Member<L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    fn is_done(&self) -> bool {
+        self.buffer.is_empty() && self.events_loop.is_closed()
+    }
+
+    // Delivers events while the deficit allows. Returns the number of events delivered, and the
+    // flow of the last callback.
+    fn serve(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) -> (usize, ControlFlow) {
+        let Member {
+            ref mut events_loop,
+            ref mut deficit,
+            ref mut buffer,
+            ref mut delivered,
+            ..
+        } = *self;
+        if buffer.is_empty() {
+            events_loop.poll_events(&mut |event| buffer.push_back(event));
+        }
+        let mut count = 0;
+        let mut flow = ControlFlow::Continue;
+        while *deficit > 0 {
+            let event = match buffer.pop_front() {
+                Some(event) => event,
+                None => break,
+            };
+            *deficit -= 1;
+            *delivered += 1;
+            count += 1;
+            flow = callback(event);
+            if flow.is_exit() {
+                return (count, flow);
+            }
+        }
+        // An idle loop can't save up its share for later.
+        if buffer.is_empty() {
+            *deficit = 0;
+        }
+        (count, flow)
+    }
+}
+
+/// An `EventsLoop` that delivers the events of several loops of the same type, giving each a
+/// share of the delivery proportional to its weight, so that a busy loop can't starve the
+/// others.
+///
+/// The loops are served in a deficit round-robin: every cycle visits each loop in turn, adds
+/// its weight to its deficit, and lets it deliver one event per unit of deficit, taking the
+/// events from the loop, and buffering them, whenever its buffer is empty. The deficit that is
+/// left carries over to the next cycle, unless the loop runs out of events, in which case it is
+/// reset, so that an idle loop doesn't save up a burst. While every loop has events, each cycle
+/// thus delivers exactly `weight` events of each loop, and over any number of cycles every loop
+/// gets its weighted share; a loop with less traffic than its share gets all of its events
+/// through. Weights of zero count as one.
+///
+/// `poll_events` runs a single cycle, so it can leave events buffered, which `pending_count`
+/// counts. `run` goes on from cycle to cycle, and otherwise polls with the same backoff as
+/// `MergeAll::run`, which is not blocking while more than one loop is left. If the callback of
+/// `run` exits, the cycle resumes where it stopped on the next call.
+///
+/// The loops keep their position for as long as the merge lives, so that `delivered` can report
+/// per-loop counters: those that report themselves closed through `EventsLoop::is_closed`, and
+/// whose buffer is empty, are skipped instead of removed. The merged loop is closed once every
+/// loop is.
+pub struct WeightedMerge<L, Event> {
+    members: Vec<Member<L, Event>>,
+    current: usize,
+    // Whether the loop at `current` already received its weight in this cycle.
+    credited: bool,
+    idle: IdleBackoff,
+}
+
+impl<L, Event> WeightedMerge<L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    /// Sets how long `run` first sleeps once every loop is idle.
+    pub fn with_idle_interval(mut self, interval: Duration) -> WeightedMerge<L, Event> {
+        self.idle = IdleBackoff::new(interval, self.idle.max);
+        self
+    }
+
+    /// Sets the longest that `run` sleeps between cycles while every loop stays idle.
+    pub fn with_max_idle_interval(mut self, interval: Duration) -> WeightedMerge<L, Event> {
+        self.idle = IdleBackoff::new(self.idle.min, interval);
+        self
+    }
+
+    /// Returns the number of events delivered from each loop so far, in the order the loops
+    /// were given to `merge_weighted`.
+    pub fn delivered(&self) -> Vec<u64> {
+        self.members.iter().map(|member| member.delivered).collect()
+    }
+
+    /// Returns the merged loops, dropping the events still buffered.
+    pub fn into_inner(self) -> Vec<L> {
+        self.members
+            .into_iter()
+            .map(|member| member.events_loop)
+            .collect()
+    }
+
+    // Serves every loop once, starting from where the previous cycle stopped. Returns the number
+    // of events delivered, and the flow of the last callback.
+    fn cycle(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) -> (usize, ControlFlow) {
+        let count = self.members.len();
+        let mut delivered = 0;
+        let mut flow = ControlFlow::Continue;
+        for _ in 0..count {
+            let member = &mut self.members[self.current];
+            if !member.is_done() {
+                if !self.credited {
+                    member.deficit += member.weight;
+                    self.credited = true;
+                }
+                let (n, last) = member.serve(callback);
+                delivered += n;
+                flow = last;
+                if flow.is_exit() {
+                    return (delivered, flow);
+                }
+            }
+            self.current = (self.current + 1) % count;
+            self.credited = false;
+        }
+        (delivered, flow)
+    }
+}
+
+impl<L, Event> EventsLoop<Event> for WeightedMerge<L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        self.cycle(&mut |event| {
+            callback(event);
+            ControlFlow::Continue
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        loop {
+            let (delivered, flow) = self.cycle(callback);
+            if flow.is_exit() {
+                return;
+            }
+            if delivered > 0 {
+                self.idle.reset();
+                continue;
+            }
+
+            let mut live = self.members.iter_mut().filter(|member| !member.is_done());
+            match (live.next(), live.next()) {
+                (None, _) => return,
+                (Some(member), None) => {
+                    let buffer = &mut member.buffer;
+                    member.events_loop.run(&mut |event| {
+                        buffer.push_back(event);
+                        ControlFlow::Break
+                    });
+                    if buffer.is_empty() {
+                        return;
+                    }
+                }
+                _ => SystemClock.sleep_until(SystemClock.now() + self.idle.next()),
+            }
+        }
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(BroadcastProxy {
+            proxies: self
+                .members
+                .iter()
+                .map(|member| member.events_loop.create_proxy())
+                .collect(),
+        })
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(BroadcastProxy {
+            proxies: self
+                .members
+                .iter()
+                .map(|member| member.events_loop.create_wakeup_proxy())
+                .collect(),
+        })
+    }
+
+    /// Counts the buffered events along with those pending in the loops.
+    fn pending_count(&self) -> Option<usize> {
+        self.members
+            .iter()
+            .map(|member| {
+                member
+                    .events_loop
+                    .pending_count()
+                    .map(|pending| pending + member.buffer.len())
+            })
+            .sum()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.members.iter().all(Member::is_done)
+    }
+
+    fn is_running(&self) -> bool {
+        self.members.iter().any(|m| m.events_loop.is_running())
+    }
+}
+
+// Wakes up all the merged loops.
+struct BroadcastProxy {
+    proxies: Vec<Box<dyn EventsLoopProxy>>,
+}
+
+impl EventsLoopProxy for BroadcastProxy {
+    // Succeeds if any loop could be woken up.
+    fn wakeup(&self) -> Result<(), EventsLoopClosed> {
+        let mut result = Err(EventsLoopClosed);
+        for proxy in &self.proxies {
+            if proxy.wakeup().is_ok() {
+                result = Ok(());
+            }
+        }
+        result
+    }
+
+    fn clone(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(BroadcastProxy {
+            proxies: self
+                .proxies
+                .iter()
+                .map(|p| EventsLoopProxy::clone(&**p))
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_all, merge_weighted};
+    use channel::ChannelEventsLoop;
+    use mock::{Event, MockEventsLoop};
+    use {ControlFlow, EventsLoop, EventsLoopPayloadProxy};
+
+    #[test]
+    fn merges_three_loops_and_removes_closed_ones() {
+        let mut merged = merge_all(vec![
+            MockEventsLoop::new(vec![1]),
+            MockEventsLoop::new(vec![10, 11, 12]),
+            MockEventsLoop::new(vec![20, 21]),
+        ]);
+
+        let mut delivered = Vec::new();
+        merged.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered, vec![1, 10, 11, 12, 20, 21]);
+        assert!(merged.is_empty());
+        assert!(merged.is_closed());
+    }
+
+    #[test]
+    fn channel_loops_are_removed_once_their_producers_are_gone() {
+        let first = ChannelEventsLoop::new();
+        let second = ChannelEventsLoop::new();
+        let (proxy, kept) = (first.create_payload_proxy(), second.create_payload_proxy());
+        proxy.wakeup_with(Event::Data(1)).unwrap();
+        let mut merged = merge_all(vec![first, second]);
+
+        drop(proxy);
+        let mut delivered = Vec::new();
+        merged.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered, vec![Event::Data(1)]);
+        assert_eq!(merged.len(), 1);
+        assert!(!merged.is_closed());
+
+        drop(kept);
+        merged.poll_events(&mut |e| delivered.push(e));
+        assert!(merged.is_closed());
+    }
+
+    #[test]
+    fn polling_rotates_the_first_loop() {
+        let mut merged = merge_all(vec![
+            ChannelEventsLoop::<Event>::new(),
+            ChannelEventsLoop::new(),
+            ChannelEventsLoop::new(),
+        ]);
+        let proxy = merged.create_proxy();
+
+        let mut rounds = Vec::new();
+        for _ in 0..3 {
+            for (n, l) in merged.loops.iter().enumerate() {
+                l.events_loop
+                    .create_payload_proxy()
+                    .wakeup_with(Event::Data(n as u32))
+                    .unwrap();
+            }
+            let mut round = Vec::new();
+            merged.poll_events(&mut |e| round.push(e));
+            rounds.push(round);
+        }
+        let data = |ns: [u32; 3]| ns.iter().map(|&n| Event::Data(n)).collect::<Vec<_>>();
+        assert_eq!(
+            rounds,
+            vec![data([0, 1, 2]), data([1, 2, 0]), data([2, 0, 1])]
+        );
+
+        proxy.wakeup().unwrap();
+        let mut woken = Vec::new();
+        merged.poll_events(&mut |e| woken.push(e));
+        assert_eq!(woken, vec![Event::Awakened; 3]);
+    }
+
+    #[test]
+    fn run_interleaves_until_every_loop_is_done() {
+        let mut merged = merge_all(vec![
+            MockEventsLoop::new(vec![1, 2]),
+            MockEventsLoop::new(vec![3]),
+            MockEventsLoop::new(vec![4, 5, 6]),
+        ]);
+
+        let mut delivered = Vec::new();
+        merged.run(&mut |e| {
+            delivered.push(e);
+            if e == 5 {
+                ControlFlow::Break
+            } else {
+                ControlFlow::Continue
+            }
+        });
+        assert_eq!(delivered, vec![1, 2, 3, 4, 5]);
+        assert!(!merged.is_closed());
+        assert_eq!(merged.pending_count(), Some(1));
+
+        merged.run(&mut |e| {
+            delivered.push(e);
+            ControlFlow::Continue
+        });
+        assert_eq!(delivered, vec![1, 2, 3, 4, 5, 6]);
+        assert!(merged.is_closed());
+    }
+
+    #[test]
+    fn nothing_is_lost_when_run_breaks_mid_round() {
+        let mut merged = merge_all(vec![
+            MockEventsLoop::new(vec![1, 2, 3]),
+            MockEventsLoop::new(vec![4, 5]),
+        ]);
+
+        let mut delivered = Vec::new();
+        merged.run(&mut |e| {
+            delivered.push(e);
+            if e == 1 {
+                ControlFlow::Break
+            } else {
+                ControlFlow::Continue
+            }
+        });
+        assert_eq!(delivered, vec![1]);
+        assert_eq!(merged.pending_count(), Some(4));
+
+        merged.run(&mut |e| {
+            delivered.push(e);
+            ControlFlow::Continue
+        });
+        assert_eq!(delivered, vec![1, 2, 3, 4, 5]);
+        assert!(merged.is_closed());
+    }
+
+    #[test]
+    fn weights_share_the_delivery_under_load() {
+        let busy = |base: u32| MockEventsLoop::new((base..base + 100).collect());
+        let mut merged = merge_weighted(vec![(busy(0), 1), (busy(100), 2), (busy(200), 4)]);
+
+        let mut total = 0;
+        merged.run(&mut |_| {
+            total += 1;
+            if total == 70 {
+                ControlFlow::Break
+            } else {
+                ControlFlow::Continue
+            }
+        });
+        assert_eq!(merged.delivered(), vec![10, 20, 40]);
+
+        // The last loop had used up its share, so the cycle `run` stopped in only has the first
+        // two loops left.
+        merged.poll_events(&mut |_| ());
+        assert_eq!(merged.delivered(), vec![11, 22, 40]);
+        assert_eq!(merged.pending_count(), Some(300 - 73));
+    }
+
+    #[test]
+    fn quiet_loops_get_all_their_events_through() {
+        let mut merged = merge_weighted(vec![
+            (MockEventsLoop::new((0..50).collect()), 1),
+            (MockEventsLoop::new(vec![100, 101]), 5),
+        ]);
+
+        let mut delivered = Vec::new();
+        merged.run(&mut |e| {
+            delivered.push(e);
+            ControlFlow::Continue
+        });
+        assert_eq!(&delivered[..4], &[0, 100, 101, 1]);
+        assert_eq!(merged.delivered(), vec![50, 2]);
+        assert!(merged.is_closed());
+    }
+}
@@ -0,0 +1,190 @@
+//! Measuring the traffic of a loop.
+
+use std::time::{Duration, Instant};
+
+use clock::{Clock, SystemClock};
+use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+// The events counted during one second, numbered from the start of the meter.
+#[derive(Copy, Clone, Default)]
+struct Bucket {
+    second: u64,
+    count: u64,
+}
+
+// A ring of per-second buckets covering the rolling window.
+struct Window {
+    start: Instant,
+    buckets: Vec<Bucket>,
+}
+
+impl Window {
+    fn new(start: Instant, seconds: usize) -> Window {
+        Window {
+            start,
+            buckets: vec![Bucket::default(); seconds],
+        }
+    }
+
+    fn second(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.start).as_secs()
+    }
+
+    fn record(&mut self, now: Instant) {
+        let second = self.second(now);
+        let len = self.buckets.len() as u64;
+        let bucket = &mut self.buckets[(second % len) as usize];
+        if bucket.second != second || bucket.count == 0 {
+            *bucket = Bucket { second, count: 0 };
+        }
+        bucket.count += 1;
+    }
+
+    fn rate(&self, now: Instant) -> f64 {
+        let second = self.second(now);
+        let len = self.buckets.len() as u64;
+        let total: u64 = self
+            .buckets
+            .iter()
+            .filter(|bucket| bucket.second + len > second)
+            .map(|bucket| bucket.count)
+            .sum();
+        // Until the window is full, only the seconds elapsed so far count.
+        total as f64 / len.min(second + 1) as f64
+    }
+}
+
+/// Wraps an `EventsLoop` and measures how many events per second it delivers, over a rolling
+/// window.
+///
+/// The window is a ring of per-second buckets: `rate_per_sec` averages the counts of the last
+/// few whole seconds, the current one included, so the figure moves by steps of one second.
+/// Until the window has been covered once, the average is taken over the seconds elapsed since
+/// the meter was built. The events and the control flow are forwarded unchanged.
+pub struct RateMeter<L, C = SystemClock> {
+    inner: L,
+    clock: C,
+    window: Window,
+}
+
+impl<L> RateMeter<L> {
+    /// Builds the adapter around `inner`, averaging over `window`, rounded down to whole seconds
+    /// and to at least one.
+    pub fn new(inner: L, window: Duration) -> RateMeter<L> {
+        let seconds = window.as_secs().max(1) as usize;
+        RateMeter {
+            inner,
+            window: Window::new(SystemClock.now(), seconds),
+            clock: SystemClock,
+        }
+    }
+}
+
+impl<L, C> RateMeter<L, C>
+where
+    C: Clock,
+{
+    /// Replaces the clock used to timestamp the events, restarting the measure.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> RateMeter<L, C2> {
+        RateMeter {
+            inner: self.inner,
+            window: Window::new(clock.now(), self.window.buckets.len()),
+            clock,
+        }
+    }
+
+    /// Returns the average number of events delivered per second over the window.
+    pub fn rate_per_sec(&self) -> f64 {
+        self.window.rate(self.clock.now())
+    }
+
+    /// Returns the wrapped loop.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+impl<L, C, Event> EventsLoop<Event> for RateMeter<L, C>
+where
+    L: EventsLoop<Event>,
+    C: Clock,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let RateMeter {
+            ref mut inner,
+            ref clock,
+            ref mut window,
+        } = *self;
+        inner.poll_events(&mut |event| {
+            window.record(clock.now());
+            callback(event);
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let RateMeter {
+            ref mut inner,
+            ref clock,
+            ref mut window,
+        } = *self;
+        inner.run(&mut |event| {
+            window.record(clock.now());
+            callback(event)
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RateMeter;
+    use clock::{Clock, MockClock};
+    use simulated::SimulatedLoop;
+    use {ControlFlow, EventsLoop};
+
+    #[test]
+    fn measures_a_steady_cadence() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut sim = SimulatedLoop::new(clock.clone());
+        // 20 events per second for 5 seconds.
+        sim.extend((0..100).map(|n| (start + Duration::from_millis(50 * n), ())));
+        let mut meter = RateMeter::new(sim, Duration::from_secs(3)).with_clock(clock.clone());
+
+        meter.run(&mut |()| ControlFlow::Continue);
+        let rate = meter.rate_per_sec();
+        assert!((rate - 20.0).abs() < 1.0, "rate of {}", rate);
+
+        // The events age out of the window.
+        clock.advance(Duration::from_secs(2));
+        let rate = meter.rate_per_sec();
+        assert!((rate - 20.0 / 3.0).abs() < 1.0, "rate of {}", rate);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(meter.rate_per_sec(), 0.0);
+    }
+}
@@ -0,0 +1,233 @@
+//! Upgrading recorded events to the current event type.
+
+use std::error::Error;
+use std::fmt;
+
+use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+type MigrateFn<'a, Old, New> = Box<dyn FnMut(Old) -> Option<New> + 'a>;
+
+/// Wraps an `EventsLoop` that produces events of an older version of the event type, such as a
+/// replay of a session recorded by a previous release, and upgrades them to the current one.
+///
+/// The migration function given to `new` maps every old event to its new form, or returns
+/// `None` for an event that has no equivalent anymore. Such events are dropped, and counted, see
+/// `dropped`, so that a replay goes on past the parts of the schema that were removed. For the
+/// replays that must be faithful, `run_strict` stops at the first event that can't be migrated
+/// instead.
+///
+/// Deserializing older record formats is left to the loop being wrapped: the crate has no
+/// serialization dependency, so the adapter works on the decoded events.
+pub struct Migrate<'a, L, Old, New> {
+    inner: L,
+    migrate: MigrateFn<'a, Old, New>,
+    seen: u64,
+    dropped: usize,
+}
+
+/// The error returned by `Migrate::run_strict` when an event can't be migrated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct UnmappedEvent {
+    /// The position of the event among those produced by the wrapped loop, from 0.
+    pub index: u64,
+}
+
+impl fmt::Display for UnmappedEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "The event at index {} can't be migrated", self.index)
+    }
+}
+
+impl Error for UnmappedEvent {}
+
+impl<'a, L, Old, New> Migrate<'a, L, Old, New>
+where
+    L: EventsLoop<Old>,
+{
+    /// Builds the adapter around `inner`, upgrading the events with `migrate`.
+    pub fn new<F>(inner: L, migrate: F) -> Migrate<'a, L, Old, New>
+    where
+        F: FnMut(Old) -> Option<New> + 'a,
+    {
+        Migrate {
+            inner,
+            migrate: Box::new(migrate),
+            seen: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Returns the number of events that were dropped because they couldn't be migrated.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Same as `run`, except that it stops at the first event that can't be migrated, and
+    /// returns its position instead of dropping it.
+    pub fn run_strict(
+        &mut self,
+        callback: &mut dyn FnMut(New) -> ControlFlow,
+    ) -> Result<(), UnmappedEvent> {
+        let Migrate {
+            ref mut inner,
+            ref mut migrate,
+            ref mut seen,
+            ..
+        } = *self;
+        let mut outcome = Ok(());
+        inner.run(&mut |event| {
+            let index = *seen;
+            *seen += 1;
+            match migrate(event) {
+                Some(event) => callback(event),
+                None => {
+                    outcome = Err(UnmappedEvent { index });
+                    ControlFlow::Break
+                }
+            }
+        });
+        outcome
+    }
+
+    /// Returns the wrapped loop.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+impl<'a, L, Old, New> EventsLoop<New> for Migrate<'a, L, Old, New>
+where
+    L: EventsLoop<Old>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(New)) {
+        let Migrate {
+            ref mut inner,
+            ref mut migrate,
+            ref mut seen,
+            ref mut dropped,
+        } = *self;
+        inner.poll_events(&mut |event| {
+            *seen += 1;
+            match migrate(event) {
+                Some(event) => callback(event),
+                None => *dropped += 1,
+            }
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(New) -> ControlFlow) {
+        let Migrate {
+            ref mut inner,
+            ref mut migrate,
+            ref mut seen,
+            ref mut dropped,
+        } = *self;
+        inner.run(&mut |event| {
+            *seen += 1;
+            match migrate(event) {
+                Some(event) => callback(event),
+                None => {
+                    *dropped += 1;
+                    ControlFlow::Continue
+                }
+            }
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Migrate, UnmappedEvent};
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop};
+
+    mod v1 {
+        #[derive(Clone, Copy)]
+        pub enum Event {
+            Key(char),
+            Click(i32, i32),
+            Idle,
+        }
+    }
+
+    mod v2 {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        pub enum Event {
+            Key(char),
+            Pointer { x: i32, y: i32 },
+        }
+    }
+
+    fn upgrade(event: v1::Event) -> Option<v2::Event> {
+        match event {
+            v1::Event::Key(c) => Some(v2::Event::Key(c)),
+            v1::Event::Click(x, y) => Some(v2::Event::Pointer { x, y }),
+            v1::Event::Idle => None,
+        }
+    }
+
+    fn recording() -> MockEventsLoop<v1::Event> {
+        MockEventsLoop::new(vec![
+            v1::Event::Key('a'),
+            v1::Event::Click(3, 4),
+            v1::Event::Idle,
+            v1::Event::Key('b'),
+        ])
+    }
+
+    #[test]
+    fn unmappable_events_are_dropped() {
+        let mut l = Migrate::new(recording(), upgrade);
+        let mut delivered = Vec::new();
+        l.run(&mut |e| {
+            delivered.push(e);
+            ControlFlow::Continue
+        });
+        assert_eq!(
+            delivered,
+            vec![
+                v2::Event::Key('a'),
+                v2::Event::Pointer { x: 3, y: 4 },
+                v2::Event::Key('b'),
+            ]
+        );
+        assert_eq!(l.dropped(), 1);
+    }
+
+    #[test]
+    fn strict_mode_stops_at_unmappable_events() {
+        let mut l = Migrate::new(recording(), upgrade);
+        let mut delivered = Vec::new();
+        let outcome = l.run_strict(&mut |e| {
+            delivered.push(e);
+            ControlFlow::Continue
+        });
+        assert_eq!(outcome, Err(UnmappedEvent { index: 2 }));
+        assert_eq!(delivered.len(), 2);
+        assert_eq!(l.pending_count(), Some(1));
+    }
+}
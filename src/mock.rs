@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+
+use {ControlFlow, EventsLoop, EventsLoopProxy, FromWakeup};
+
+/// An event type for the tests of the loops that need `FromWakeup`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Event {
+    Awakened,
+    Data(u32),
+}
+
+impl FromWakeup for Event {
+    fn from_wakeup() -> Event {
+        Event::Awakened
+    }
+
+    fn is_wakeup(&self) -> bool {
+        *self == Event::Awakened
+    }
+}
+
+/// An `EventsLoop` that delivers a fixed queue of events, used by the tests of the adapters.
+///
+/// `poll_events` delivers everything that is queued, and `run` returns once the queue is empty
+/// instead of blocking.
+pub struct MockEventsLoop<T> {
+    events: VecDeque<T>,
+}
+
+impl<T> MockEventsLoop<T> {
+    pub fn new(events: Vec<T>) -> MockEventsLoop<T> {
+        MockEventsLoop {
+            events: events.into(),
+        }
+    }
+
+    pub fn push(&mut self, event: T) {
+        self.events.push_back(event);
+    }
+}
+
+impl<T> EventsLoop<T> for MockEventsLoop<T> {
+    fn poll_events(&mut self, callback: &mut dyn FnMut(T)) {
+        while let Some(event) = self.events.pop_front() {
+            callback(event);
+        }
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(T) -> ControlFlow) {
+        while let Some(event) = self.events.pop_front() {
+            if callback(event).is_exit() {
+                return;
+            }
+        }
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        unimplemented!();
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        Some(self.events.len())
+    }
+
+    // The loop can't be fed once built, so it is done once its queue is empty.
+    fn is_closed(&self) -> bool {
+        self.events.is_empty()
+    }
+}
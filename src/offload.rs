@@ -0,0 +1,204 @@
+//! Moving event processing off the thread of the `EventsLoop`.
+
+use std::sync::mpsc::Sender;
+
+use {ControlFlow, DropSink, EventsLoop, EventsLoopProxy};
+
+/// Wraps an `EventsLoop` and sends a clone of every event it produces through a `Sender`.
+///
+/// Since an `EventsLoop` can't be sent to another thread, heavy per-event work would otherwise
+/// have to happen in the callback, delaying the moment the loop gets back to the system queue.
+/// With `OffloadTo`, the loop thread only clones the event into the channel and calls the
+/// callback as usual, while a worker thread of your choosing receives the clones and does the
+/// real work. This decouples event latency (how quickly the loop drains the queue) from
+/// processing latency (how long the worker takes for each event).
+///
+/// Once the `Receiver` of the channel has been dropped, events are no longer forwarded and `run`
+/// returns as if the callback had returned `ControlFlow::Break`. The event that `run` had taken
+/// from the inner loop when it noticed is neither forwarded nor delivered, and is reported to the
+/// `DropSink` given to `with_drop_sink`, if any. `poll_events` can't stop early, so it keeps
+/// calling the callback for the remaining events.
+///
+/// # Example
+///
+/// ```
+/// use std::sync::mpsc;
+/// use std::thread;
+///
+/// use events_loop::{ControlFlow, EventsLoop};
+/// use events_loop::offload::OffloadTo;
+///
+/// #[derive(Clone, Debug)]
+/// enum Event {
+///     Packet(Vec<u8>),
+///     Quit,
+/// }
+///
+/// fn run_offloaded<L: EventsLoop<Event>>(events_loop: L) {
+///     let (sender, receiver) = mpsc::channel();
+///
+///     let worker = thread::spawn(move || {
+///         for event in receiver {
+///             if let Event::Packet(bytes) = event {
+///                 let checksum = bytes.iter().fold(0u32, |sum, &b| sum.wrapping_add(b as u32));
+///                 println!("checksum: {}", checksum);
+///             }
+///         }
+///     });
+///
+///     let mut events_loop = OffloadTo::new(events_loop, sender);
+///     events_loop.run(&mut |event| match event {
+///         Event::Quit => ControlFlow::Break,
+///         Event::Packet(_) => ControlFlow::Continue,
+///     });
+///
+///     // Dropping the adapter closes the channel, which lets the worker finish.
+///     drop(events_loop);
+///     worker.join().unwrap();
+/// }
+/// ```
+pub struct OffloadTo<L, Event> {
+    inner: L,
+    sender: Sender<Event>,
+    disconnected: bool,
+    drop_sink: Option<DropSink<Event>>,
+}
+
+impl<L, Event> OffloadTo<L, Event> {
+    /// Builds the adapter around `inner`, forwarding clones of its events to `sender`.
+    pub fn new(inner: L, sender: Sender<Event>) -> OffloadTo<L, Event> {
+        OffloadTo {
+            inner,
+            sender,
+            disconnected: false,
+            drop_sink: None,
+        }
+    }
+
+    /// Reports the event that `run` couldn't deliver once the channel is closed to `sink`.
+    pub fn with_drop_sink(mut self, sink: DropSink<Event>) -> OffloadTo<L, Event> {
+        self.drop_sink = Some(sink);
+        self
+    }
+
+    /// Returns `true` if the `Receiver` of the channel has been dropped.
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected
+    }
+
+    /// Returns the wrapped loop.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+fn forward<Event: Clone>(sender: &Sender<Event>, disconnected: &mut bool, event: &Event) {
+    if !*disconnected && sender.send(event.clone()).is_err() {
+        *disconnected = true;
+    }
+}
+
+impl<L, Event> EventsLoop<Event> for OffloadTo<L, Event>
+where
+    L: EventsLoop<Event>,
+    Event: Send + Clone,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let sender = &self.sender;
+        let disconnected = &mut self.disconnected;
+        self.inner.poll_events(&mut |event| {
+            forward(sender, disconnected, &event);
+            callback(event);
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        if self.disconnected {
+            return;
+        }
+
+        let OffloadTo {
+            ref mut inner,
+            ref sender,
+            ref mut disconnected,
+            ref drop_sink,
+        } = *self;
+        inner.run(&mut |event| {
+            forward(sender, disconnected, &event);
+            if *disconnected {
+                if let Some(ref drop_sink) = *drop_sink {
+                    (*drop_sink.borrow_mut())(event);
+                }
+                return ControlFlow::Break;
+            }
+            callback(event)
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+
+    use super::OffloadTo;
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop};
+
+    #[test]
+    fn forwards_clones_and_delivers_originals() {
+        let (sender, receiver) = mpsc::channel();
+        let mut l = OffloadTo::new(MockEventsLoop::new(vec![1, 2, 3]), sender);
+
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |e| delivered.push(e));
+
+        assert_eq!(delivered, vec![1, 2, 3]);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn breaks_when_receiver_dropped() {
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let sink = dropped.clone();
+        let mut l = OffloadTo::new(MockEventsLoop::new(vec![1, 2, 3]), sender)
+            .with_drop_sink(Rc::new(RefCell::new(move |e| sink.borrow_mut().push(e))));
+
+        let mut delivered = Vec::new();
+        l.run(&mut |e| {
+            delivered.push(e);
+            ControlFlow::Continue
+        });
+
+        assert!(delivered.is_empty());
+        assert_eq!(*dropped.borrow(), vec![1]);
+        assert!(l.is_disconnected());
+    }
+}
@@ -0,0 +1,300 @@
+//! Delivering events at a steady rate.
+
+use std::time::{Duration, Instant};
+
+use budget::MemoryBudget;
+use clock::{Clock, SystemClock};
+use queue::BoundedQueue;
+pub use queue::DEFAULT_CAPACITY;
+use {ControlFlow, DropSink, EventsLoop, EventsLoopProxy, OverflowPolicy};
+
+/// Wraps an `EventsLoop` and spaces out the delivery of its events, so that they come out at
+/// most once per interval, however fast the source produces them.
+///
+/// Unlike a throttle, which drops events, `Pace` buffers them and hands them out one slot at a
+/// time. The buffer is bounded, and once it holds `capacity` events the `OverflowPolicy` decides
+/// which event is discarded. The source can be much faster than the pace, so pick the capacity
+/// and the policy with that in mind, or share a `MemoryBudget` with the other buffers of the
+/// pipeline.
+///
+/// `poll_events` never blocks: it buffers whatever the inner loop has pending and delivers the
+/// events whose slot has come. `run` sleeps on the clock until the next slot, as a
+/// `ControlFlow::WaitUntil` deadline would, and waits on the inner loop when the buffer is empty.
+/// It returns if the inner loop returns on its own while the buffer is empty. If the callback
+/// returns `ControlFlow::WaitUntil`, `run` wakes up at the earlier of that deadline and the next
+/// slot, and doesn't wait on the inner loop before the deadline has passed.
+pub struct Pace<L, Event, C = SystemClock> {
+    inner: L,
+    clock: C,
+    interval: Duration,
+    buffer: BoundedQueue<Event>,
+    next_slot: Option<Instant>,
+}
+
+impl<L, Event> Pace<L, Event> {
+    /// Builds the adapter around `inner`, delivering at most one event per `interval`.
+    pub fn new(inner: L, interval: Duration) -> Pace<L, Event> {
+        Pace {
+            inner,
+            clock: SystemClock,
+            interval,
+            buffer: BoundedQueue::new(DEFAULT_CAPACITY, OverflowPolicy::DropOldest),
+            next_slot: None,
+        }
+    }
+
+    /// Builds the adapter around `inner`, delivering at most `rate` events per second.
+    pub fn per_second(inner: L, rate: u32) -> Pace<L, Event> {
+        Pace::new(inner, Duration::from_secs(1) / rate.max(1))
+    }
+}
+
+impl<L, Event, C> Pace<L, Event, C> {
+    /// Bounds the buffer to `capacity` events, applying `policy` when it is full.
+    pub fn with_capacity(mut self, capacity: usize, policy: OverflowPolicy) -> Pace<L, Event, C> {
+        self.buffer.set_bounds(capacity, policy);
+        self
+    }
+
+    /// Reports the events discarded from the buffer to `sink`, see `DropSink`.
+    pub fn with_drop_sink(mut self, sink: DropSink<Event>) -> Pace<L, Event, C> {
+        self.buffer.set_drop_sink(sink);
+        self
+    }
+
+    /// Draws the memory of the buffered events from `budget`, see `MemoryBudget`.
+    pub fn with_memory_budget(mut self, budget: MemoryBudget<Event>) -> Pace<L, Event, C> {
+        self.buffer.set_budget(budget);
+        self
+    }
+
+    /// Replaces the clock used to compute and wait for the slots.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> Pace<L, Event, C2> {
+        Pace {
+            inner: self.inner,
+            clock,
+            interval: self.interval,
+            buffer: self.buffer,
+            next_slot: self.next_slot,
+        }
+    }
+
+    /// Returns the number of events waiting for their slot.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+impl<L, Event, C> Pace<L, Event, C>
+where
+    L: EventsLoop<Event>,
+    C: Clock,
+{
+    fn fill(&mut self) {
+        let buffer = &mut self.buffer;
+        self.inner.poll_events(&mut |event| buffer.push(event));
+    }
+
+    // Delivers the events whose slot has come, and returns the flow of the last callback.
+    fn deliver_due(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) -> ControlFlow {
+        let mut flow = ControlFlow::Continue;
+        while !self.buffer.is_empty() && !flow.is_exit() {
+            let now = self.clock.now();
+            let slot = self.next_slot.unwrap_or(now);
+            if slot > now {
+                break;
+            }
+            // A late delivery shifts the next slots instead of bursting to catch up.
+            self.next_slot = Some(now + self.interval);
+            if let Some(event) = self.buffer.pop() {
+                flow = callback(event);
+            }
+        }
+        flow
+    }
+}
+
+impl<L, Event, C> EventsLoop<Event> for Pace<L, Event, C>
+where
+    L: EventsLoop<Event>,
+    C: Clock,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        self.fill();
+        self.deliver_due(&mut |event| {
+            callback(event);
+            ControlFlow::Continue
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let mut wake_at = None;
+        loop {
+            self.fill();
+            match self.deliver_due(callback) {
+                ControlFlow::WaitUntil(deadline) => wake_at = Some(deadline),
+                flow if flow.is_exit() => return,
+                _ => {}
+            }
+            if wake_at.is_some_and(|deadline| deadline <= self.clock.now()) {
+                wake_at = None;
+            }
+
+            let slot = match (self.buffer.is_empty(), self.next_slot) {
+                (false, Some(slot)) => Some(slot),
+                _ => None,
+            };
+            let wake = match (slot, wake_at) {
+                (Some(slot), Some(deadline)) => Some(slot.min(deadline)),
+                (slot, deadline) => slot.or(deadline),
+            };
+            if let Some(wake) = wake {
+                self.clock.sleep_until(wake);
+                continue;
+            }
+
+            let buffer = &mut self.buffer;
+            let mut received = false;
+            self.inner.run(&mut |event| {
+                buffer.push(event);
+                received = true;
+                ControlFlow::Break
+            });
+            if !received {
+                return;
+            }
+        }
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    /// Returns the headroom of the buffer, or that of the wrapped loop if it is smaller.
+    fn remaining_capacity(&self) -> Option<usize> {
+        let own = self.buffer.headroom();
+        Some(
+            self.inner
+                .remaining_capacity()
+                .map_or(own, |inner| inner.min(own)),
+        )
+    }
+
+    /// Returns `true` once the inner loop is closed and the buffer is empty.
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed() && self.buffer.is_empty()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    /// Counts the buffered events along with those pending in the inner loop.
+    fn pending_count(&self) -> Option<usize> {
+        self.inner
+            .pending_count()
+            .map(|pending| pending + self.buffer.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::Pace;
+    use clock::{Clock, MockClock};
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop, OverflowPolicy};
+
+    #[test]
+    fn spaces_out_delivery() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let interval = Duration::from_secs(1) / 30;
+        let mut paced = Pace::per_second(MockEventsLoop::new(vec![1, 2, 3, 4, 5]), 30)
+            .with_clock(clock.clone());
+
+        let mut delivered = Vec::new();
+        paced.run(&mut |event| {
+            delivered.push((event, clock.now() - start));
+            ControlFlow::Continue
+        });
+
+        assert_eq!(delivered.len(), 5);
+        for (i, &(event, at)) in delivered.iter().enumerate() {
+            assert_eq!(event, i + 1);
+            let expected = interval * i as u32;
+            let error = at.abs_diff(expected);
+            assert!(
+                error < Duration::from_millis(1),
+                "event {} at {:?}",
+                event,
+                at
+            );
+        }
+    }
+
+    #[test]
+    fn wait_until_is_honored() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut paced = Pace::new(MockEventsLoop::new(vec![1, 2]), Duration::from_millis(10))
+            .with_clock(clock.clone());
+
+        let mut delivered = Vec::new();
+        paced.run(&mut |event| {
+            delivered.push((event, clock.now() - start));
+            // Earlier than the next slot for the first event, later for the second.
+            ControlFlow::WaitUntil(start + Duration::from_millis(if event == 1 { 4 } else { 50 }))
+        });
+
+        let ms = Duration::from_millis;
+        assert_eq!(delivered, vec![(1, ms(0)), (2, ms(10))]);
+        assert_eq!(clock.now(), start + ms(50));
+    }
+
+    #[test]
+    fn overflow_policy_bounds_the_buffer() {
+        let clock = MockClock::new();
+        let mut paced = Pace::new(
+            MockEventsLoop::new(vec![1, 2, 3, 4, 5]),
+            Duration::from_millis(10),
+        )
+        .with_capacity(2, OverflowPolicy::DropOldest)
+        .with_clock(clock.clone());
+
+        let mut delivered = Vec::new();
+        paced.poll_events(&mut |event| delivered.push(event));
+        assert_eq!(delivered, vec![4]);
+        assert_eq!(paced.buffered(), 1);
+
+        paced.poll_events(&mut |event| delivered.push(event));
+        assert_eq!(delivered, vec![4]);
+
+        clock.advance(Duration::from_millis(10));
+        paced.poll_events(&mut |event| delivered.push(event));
+        assert_eq!(delivered, vec![4, 5]);
+    }
+
+    #[test]
+    fn drops_are_reported_to_the_sink() {
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let sink = dropped.clone();
+        let mut paced = Pace::new(
+            MockEventsLoop::new(vec![1, 2, 3, 4]),
+            Duration::from_secs(1),
+        )
+        .with_capacity(1, OverflowPolicy::DropNewest)
+        .with_drop_sink(Rc::new(RefCell::new(move |e| sink.borrow_mut().push(e))))
+        .with_clock(MockClock::new());
+
+        paced.poll_events(&mut |_| ());
+        assert_eq!(*dropped.borrow(), vec![2, 3, 4]);
+    }
+}
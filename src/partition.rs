@@ -0,0 +1,133 @@
+//! Routing events to one of two callbacks.
+
+use {ControlFlow, EventsLoop};
+
+/// Drives an `EventsLoop` with two callbacks, one for the events that match a predicate and one
+/// for the others.
+///
+/// Events are dispatched in the order they arrive, each to whichever side its predicate result
+/// selects. The control flow is the union of both sides: `run` returns as soon as either callback
+/// returns `ControlFlow::Break` or `ControlFlow::ExitWithCode`.
+pub struct Partition<'a, L, Event> {
+    inner: L,
+    predicate: Box<dyn FnMut(&Event) -> bool + 'a>,
+    matched: Box<dyn FnMut(Event) -> ControlFlow + 'a>,
+    unmatched: Box<dyn FnMut(Event) -> ControlFlow + 'a>,
+}
+
+impl<'a, L, Event> Partition<'a, L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    /// Builds the partition of the events of `inner`.
+    pub fn new<P, M, U>(inner: L, predicate: P, matched: M, unmatched: U) -> Partition<'a, L, Event>
+    where
+        P: FnMut(&Event) -> bool + 'a,
+        M: FnMut(Event) -> ControlFlow + 'a,
+        U: FnMut(Event) -> ControlFlow + 'a,
+    {
+        Partition {
+            inner,
+            predicate: Box::new(predicate),
+            matched: Box::new(matched),
+            unmatched: Box::new(unmatched),
+        }
+    }
+
+    /// Dispatches the pending events, like `EventsLoop::poll_events`. The control flow returned
+    /// by the callbacks is ignored.
+    pub fn poll_events(&mut self) {
+        let Partition {
+            ref mut inner,
+            ref mut predicate,
+            ref mut matched,
+            ref mut unmatched,
+        } = *self;
+        inner.poll_events(&mut |event| {
+            if predicate(&event) {
+                matched(event);
+            } else {
+                unmatched(event);
+            }
+        });
+    }
+
+    /// Dispatches events until either callback breaks, like `EventsLoop::run`.
+    pub fn run(&mut self) {
+        let Partition {
+            ref mut inner,
+            ref mut predicate,
+            ref mut matched,
+            ref mut unmatched,
+        } = *self;
+        inner.run(&mut |event| {
+            if predicate(&event) {
+                matched(event)
+            } else {
+                unmatched(event)
+            }
+        });
+    }
+
+    /// Returns the wrapped loop.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::Partition;
+    use mock::MockEventsLoop;
+    use ControlFlow;
+
+    #[test]
+    fn routes_by_predicate_in_arrival_order() {
+        let evens = RefCell::new(Vec::new());
+        let odds = RefCell::new(Vec::new());
+        {
+            let mut partition = Partition::new(
+                MockEventsLoop::new(vec![1, 2, 3, 4, 5]),
+                |e: &i32| e % 2 == 0,
+                |e| {
+                    evens.borrow_mut().push(e);
+                    ControlFlow::Continue
+                },
+                |e| {
+                    odds.borrow_mut().push(e);
+                    ControlFlow::Continue
+                },
+            );
+            partition.poll_events();
+        }
+        assert_eq!(evens.into_inner(), vec![2, 4]);
+        assert_eq!(odds.into_inner(), vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn either_side_can_break() {
+        let mut seen = Vec::new();
+        {
+            let mut partition = Partition::new(
+                MockEventsLoop::new(vec![1, 2, 3, 4]),
+                |e: &i32| e % 2 == 0,
+                |_| ControlFlow::Continue,
+                |e| {
+                    seen.push(e);
+                    if e == 3 {
+                        ControlFlow::Break
+                    } else {
+                        ControlFlow::Continue
+                    }
+                },
+            );
+            partition.run();
+            let mut rest = Vec::new();
+            ::EventsLoop::poll_events(&mut partition.into_inner(), &mut |e| rest.push(e));
+            assert_eq!(rest, vec![4]);
+        }
+        assert_eq!(seen, vec![1, 3]);
+    }
+}
@@ -0,0 +1,150 @@
+//! Composing many same-type transformation stages without nesting adapter types.
+
+use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+type Stage<'a, Event> = Box<dyn FnMut(Event) -> Option<Event> + 'a>;
+
+/// Collects boxed transformation stages, to be applied in order to the events of a loop.
+///
+/// Each stage receives the event returned by the previous one, and can modify it, replace it, or
+/// drop it by returning `None`, in which case the following stages are skipped. Compared to
+/// nesting one adapter per step, the stages are boxed and called through a pointer, which costs a
+/// little performance but keeps the type of the resulting loop simple however many stages there
+/// are. Since a stage owns the events it drops, it can report them to a `DropSink` itself.
+///
+/// ```
+/// use events_loop::EventsLoop;
+/// use events_loop::pipeline::{Piped, Pipeline};
+///
+/// fn scaled<'a, L: EventsLoop<i32>>(events_loop: L) -> Piped<'a, L, i32> {
+///     Pipeline::new()
+///         .stage(|n| if n >= 0 { Some(n) } else { None })
+///         .stage(|n| Some(n * 10))
+///         .build(events_loop)
+/// }
+/// ```
+pub struct Pipeline<'a, Event> {
+    stages: Vec<Stage<'a, Event>>,
+}
+
+impl<'a, Event> Default for Pipeline<'a, Event> {
+    fn default() -> Pipeline<'a, Event> {
+        Pipeline::new()
+    }
+}
+
+impl<'a, Event> Pipeline<'a, Event> {
+    /// Builds a pipeline without any stage, which passes events through unchanged.
+    pub fn new() -> Pipeline<'a, Event> {
+        Pipeline { stages: Vec::new() }
+    }
+
+    /// Appends a stage to the pipeline.
+    pub fn stage<F>(mut self, stage: F) -> Pipeline<'a, Event>
+    where
+        F: FnMut(Event) -> Option<Event> + 'a,
+    {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Applies the pipeline to the events of `inner`.
+    pub fn build<L: EventsLoop<Event>>(self, inner: L) -> Piped<'a, L, Event> {
+        Piped {
+            inner,
+            stages: self.stages,
+        }
+    }
+}
+
+/// An `EventsLoop` whose events went through the stages of a `Pipeline`.
+pub struct Piped<'a, L, Event> {
+    inner: L,
+    stages: Vec<Stage<'a, Event>>,
+}
+
+impl<'a, L, Event> Piped<'a, L, Event> {
+    /// Returns the wrapped loop.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+fn apply<Event>(stages: &mut [Stage<Event>], event: Event) -> Option<Event> {
+    stages
+        .iter_mut()
+        .try_fold(event, |event, stage| stage(event))
+}
+
+impl<'a, L, Event> EventsLoop<Event> for Piped<'a, L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let stages = &mut self.stages;
+        self.inner.poll_events(&mut |event| {
+            if let Some(event) = apply(stages, event) {
+                callback(event);
+            }
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let stages = &mut self.stages;
+        self.inner.run(&mut |event| match apply(stages, event) {
+            Some(event) => callback(event),
+            None => ControlFlow::Continue,
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pipeline;
+    use mock::MockEventsLoop;
+    use EventsLoop;
+
+    #[test]
+    fn stages_apply_in_order() {
+        let mut seen_by_last = 0;
+        {
+            let mut l = Pipeline::new()
+                .stage(|n: i32| if n % 2 == 0 { Some(n) } else { None })
+                .stage(|n| Some(n + 1))
+                .stage(|n| {
+                    seen_by_last += 1;
+                    Some(n * 10)
+                })
+                .build(MockEventsLoop::new(vec![1, 2, 3, 4]));
+
+            let mut delivered = Vec::new();
+            l.poll_events(&mut |e| delivered.push(e));
+            assert_eq!(delivered, vec![30, 50]);
+        }
+        assert_eq!(seen_by_last, 2);
+    }
+}
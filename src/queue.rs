@@ -0,0 +1,118 @@
+use std::collections::VecDeque;
+use std::mem;
+
+use budget::MemoryBudget;
+use {DropSink, OverflowPolicy};
+
+/// Capacity of the buffers of `gate::Gate` and `pace::Pace`, and of every lane of
+/// `laned::LanedLoop`, unless configured otherwise.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// The bounded buffer shared by the adapters that hold events back.
+pub(crate) struct BoundedQueue<Event> {
+    events: VecDeque<Event>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    drop_sink: Option<DropSink<Event>>,
+    budget: Option<MemoryBudget<Event>>,
+}
+
+impl<Event> BoundedQueue<Event> {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> BoundedQueue<Event> {
+        BoundedQueue {
+            events: VecDeque::new(),
+            capacity,
+            policy,
+            drop_sink: None,
+            budget: None,
+        }
+    }
+
+    pub fn set_bounds(&mut self, capacity: usize, policy: OverflowPolicy) {
+        self.capacity = capacity;
+        self.policy = policy;
+    }
+
+    pub fn set_drop_sink(&mut self, sink: DropSink<Event>) {
+        self.drop_sink = Some(sink);
+    }
+
+    /// Draws the memory of the queued events from `budget` instead of the previous one, if any.
+    ///
+    /// The queued events are pushed again, oldest first, so those that `budget` can't cover are
+    /// discarded under the overflow policy, as new events would be.
+    pub fn set_budget(&mut self, budget: MemoryBudget<Event>) {
+        let queued = mem::take(&mut self.events);
+        if let Some(ref previous) = self.budget {
+            for event in &queued {
+                previous.release(previous.size_of(event));
+            }
+        }
+        self.budget = Some(budget);
+        for event in queued {
+            self.push(event);
+        }
+    }
+
+    /// Queues `event`, applying the overflow policy when the queue is full or the memory budget
+    /// is exhausted, and reporting the discarded event to the drop sink.
+    pub fn push(&mut self, event: Event) {
+        if let Some(budget) = self.budget.clone() {
+            let size = budget.size_of(&event);
+            while !budget.reserve(size) {
+                let dropped = match self.policy {
+                    OverflowPolicy::DropOldest => self.events.pop_front(),
+                    _ => None,
+                };
+                match dropped {
+                    Some(dropped) => self.discard(dropped),
+                    None => return self.report(event),
+                }
+            }
+        }
+        if let Some(dropped) = self.policy.push(&mut self.events, self.capacity, event) {
+            self.discard(dropped);
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Event> {
+        let event = self.events.pop_front();
+        if let (Some(ref budget), Some(ref event)) = (&self.budget, &event) {
+            budget.release(budget.size_of(event));
+        }
+        event
+    }
+
+    // Releases the memory of an event that was counted against the budget, and reports it.
+    fn discard(&mut self, event: Event) {
+        if let Some(ref budget) = self.budget {
+            budget.release(budget.size_of(&event));
+        }
+        self.report(event);
+    }
+
+    fn report(&self, event: Event) {
+        if let Some(ref sink) = self.drop_sink {
+            (*sink.borrow_mut())(event);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Returns the number of events the queue can take before the overflow policy applies.
+    pub fn headroom(&self) -> usize {
+        self.capacity.saturating_sub(self.events.len())
+    }
+}
+
+impl<Event> Drop for BoundedQueue<Event> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
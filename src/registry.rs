@@ -0,0 +1,76 @@
+//! Proxies created up front for a known set of producers.
+
+use std::collections::HashMap;
+
+use {EventsLoop, EventsLoopProxy};
+
+/// A set of proxies of one `EventsLoop`, created up front and retrieved by name.
+///
+/// In applications with a fixed set of producer threads, this gives each of them a well-known
+/// endpoint, instead of creating proxies ad hoc and threading them through. The registry only
+/// holds proxies, so it is `Send` and can be shared with the code that spawns the producers.
+///
+/// The crate has no loop builder to hang this on, so the registry is built from the loop
+/// itself with `ProxyRegistry::new`. As with any proxy, the ones it hands out stay valid for as
+/// long as the loop exists, and return `EventsLoopClosed` afterwards.
+pub struct ProxyRegistry {
+    proxies: HashMap<String, Box<dyn EventsLoopProxy>>,
+}
+
+impl ProxyRegistry {
+    /// Creates one proxy of `events_loop` for each of `names`. A name given twice gets a single
+    /// proxy.
+    pub fn new<L, Event>(events_loop: &L, names: &[&str]) -> ProxyRegistry
+    where
+        L: EventsLoop<Event>,
+    {
+        ProxyRegistry {
+            proxies: names
+                .iter()
+                .map(|&name| (name.to_owned(), events_loop.create_proxy()))
+                .collect(),
+        }
+    }
+
+    /// Returns a clone of the proxy registered as `name`, or `None` if there is no such name.
+    pub fn proxy(&self, name: &str) -> Option<Box<dyn EventsLoopProxy>> {
+        self.proxies.get(name).cloned()
+    }
+
+    /// Returns the registered names, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.proxies.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::ProxyRegistry;
+    use channel::ChannelEventsLoop;
+    use mock::Event;
+    use EventsLoop;
+
+    #[test]
+    fn named_proxies_wake_the_loop() {
+        let mut l = ChannelEventsLoop::<Event>::new();
+        let registry = ProxyRegistry::new(&l, &["network", "audio"]);
+        assert!(registry.proxy("video").is_none());
+
+        let producers: Vec<_> = ["network", "audio"]
+            .iter()
+            .map(|&name| {
+                let proxy = registry.proxy(name).unwrap();
+                thread::spawn(move || proxy.wakeup().unwrap())
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(delivered, vec![Event::Awakened, Event::Awakened]);
+    }
+}
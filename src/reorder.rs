@@ -0,0 +1,352 @@
+//! Restoring the order of events that arrive slightly out of sequence.
+
+use std::collections::BTreeMap;
+use std::mem;
+use std::ops::Range;
+
+use budget::MemoryBudget;
+use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+type GapHook<'a> = Box<dyn FnMut(Range<u64>) + 'a>;
+type LateHook<'a, Event> = Box<dyn FnMut(&Event) + 'a>;
+
+// The events held back until their turn, and the hooks that report anomalies.
+struct Window<'a, Event> {
+    size: usize,
+    next: Option<u64>,
+    held: BTreeMap<u64, Event>,
+    on_gap: Option<GapHook<'a>>,
+    on_late: Option<LateHook<'a, Event>>,
+    budget: Option<MemoryBudget<Event>>,
+    // The sequence numbers and sizes of the held events that the budget couldn't cover yet.
+    unpaid: Vec<(u64, usize)>,
+}
+
+impl<'a, Event> Window<'a, Event> {
+    // Holds `event` back, or delivers it right away if it is late, then releases the events
+    // whose turn has come.
+    fn accept(
+        &mut self,
+        sequence: u64,
+        event: Event,
+        callback: &mut dyn FnMut(Event) -> ControlFlow,
+    ) -> ControlFlow {
+        let late = match self.next {
+            Some(next) => sequence < next || self.held.contains_key(&sequence),
+            None => self.held.contains_key(&sequence),
+        };
+        if late {
+            if let Some(ref mut on_late) = self.on_late {
+                on_late(&event);
+            }
+            let flow = callback(event);
+            if flow.is_exit() {
+                return flow;
+            }
+        } else {
+            if let Some(ref budget) = self.budget {
+                let size = budget.size_of(&event);
+                if !budget.reserve(size) {
+                    self.unpaid.push((sequence, size));
+                }
+            }
+            self.held.insert(sequence, event);
+        }
+        self.release(callback)
+    }
+
+    // Delivers the held events that come next in sequence, and the oldest ones while more than
+    // `size` are held or the budget can't cover them, reporting the sequence numbers skipped in
+    // doing so.
+    fn release(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) -> ControlFlow {
+        self.release_while(callback, |window, first| {
+            window.next == Some(first)
+                || window.held.len() > window.size
+                || !window.unpaid.is_empty()
+        })
+    }
+
+    // Takes the bytes of the unpaid events out of the budget, as far as it allows.
+    fn pay(&mut self) {
+        if let Some(ref budget) = self.budget {
+            self.unpaid.retain(|&(_, size)| !budget.reserve(size));
+        }
+    }
+
+    // Returns the bytes of a held event that leaves the window to the budget, if they were paid.
+    fn settle(&mut self, sequence: u64, event: &Event) {
+        let unpaid = self.unpaid.len();
+        self.unpaid.retain(|&(held, _)| held != sequence);
+        if self.unpaid.len() < unpaid {
+            return;
+        }
+        if let Some(ref budget) = self.budget {
+            budget.release(budget.size_of(event));
+        }
+    }
+
+    // Delivers every held event, in sequence order.
+    fn flush(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) -> ControlFlow {
+        self.release_while(callback, |_, _| true)
+    }
+
+    fn release_while(
+        &mut self,
+        callback: &mut dyn FnMut(Event) -> ControlFlow,
+        due: fn(&Window<'a, Event>, u64) -> bool,
+    ) -> ControlFlow {
+        loop {
+            self.pay();
+            let first = match self.held.keys().next() {
+                Some(&first) if due(self, first) => first,
+                _ => return ControlFlow::Continue,
+            };
+            if let (Some(next), Some(ref mut on_gap)) = (self.next, self.on_gap.as_mut()) {
+                if first > next {
+                    on_gap(next..first);
+                }
+            }
+            self.next = Some(first + 1);
+            if let Some(event) = self.held.remove(&first) {
+                self.settle(first, &event);
+                let flow = callback(event);
+                if flow.is_exit() {
+                    return flow;
+                }
+            }
+        }
+    }
+}
+
+impl<'a, Event> Drop for Window<'a, Event> {
+    fn drop(&mut self) {
+        let held = mem::take(&mut self.held);
+        for (sequence, event) in held {
+            self.settle(sequence, &event);
+        }
+    }
+}
+
+/// Wraps an `EventsLoop` whose events carry a monotonic sequence number, and delivers them in
+/// sequence order.
+///
+/// Up to `window` events are held back while an earlier one is missing. An event is released as
+/// soon as it is the next one in sequence, or when the window overflows, in which case the
+/// missing sequence numbers are given up on and reported to the hook given to `on_gap`. An event
+/// that arrives after its turn, because it is too late for the window or is a duplicate, is
+/// delivered right away, and reported to the hook given to `on_late` first.
+///
+/// Until the first release, the sequence doesn't have a known start, so the first events are
+/// held until the window overflows; `starting_at` gives the first expected sequence number
+/// instead. Events still held when the source dries up stay held, see `flush`.
+pub struct Reorder<'a, L, Event> {
+    inner: L,
+    sequence: Box<dyn FnMut(&Event) -> u64 + 'a>,
+    window: Window<'a, Event>,
+}
+
+impl<'a, L, Event> Reorder<'a, L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    /// Builds the adapter around `inner`, reading the sequence numbers with `sequence` and
+    /// holding back at most `window` events.
+    pub fn new<F>(inner: L, sequence: F, window: usize) -> Reorder<'a, L, Event>
+    where
+        F: FnMut(&Event) -> u64 + 'a,
+    {
+        Reorder {
+            inner,
+            sequence: Box::new(sequence),
+            window: Window {
+                size: window,
+                next: None,
+                held: BTreeMap::new(),
+                on_gap: None,
+                on_late: None,
+                budget: None,
+                unpaid: Vec::new(),
+            },
+        }
+    }
+
+    /// Expects the sequence to start at `first`.
+    pub fn starting_at(mut self, first: u64) -> Reorder<'a, L, Event> {
+        self.window.next = Some(first);
+        self
+    }
+
+    /// Reports the ranges of sequence numbers that were skipped to `hook`.
+    pub fn on_gap<F>(mut self, hook: F) -> Reorder<'a, L, Event>
+    where
+        F: FnMut(Range<u64>) + 'a,
+    {
+        self.window.on_gap = Some(Box::new(hook));
+        self
+    }
+
+    /// Shows the events that arrived after their turn to `hook`, right before delivering them.
+    pub fn on_late<F>(mut self, hook: F) -> Reorder<'a, L, Event>
+    where
+        F: FnMut(&Event) + 'a,
+    {
+        self.window.on_late = Some(Box::new(hook));
+        self
+    }
+
+    /// Draws the memory of the held events from `budget`, see `MemoryBudget`. Reorder never
+    /// discards an event: when the budget can't cover the events it holds, the oldest ones are
+    /// released early, as when the window overflows.
+    pub fn with_memory_budget(mut self, budget: MemoryBudget<Event>) -> Reorder<'a, L, Event> {
+        self.window.budget = Some(budget);
+        self
+    }
+
+    /// Returns the number of events held back.
+    pub fn held(&self) -> usize {
+        self.window.held.len()
+    }
+
+    /// Delivers every held event in sequence order, reporting the gaps between them, for
+    /// example once the source is done.
+    pub fn flush(&mut self, callback: &mut dyn FnMut(Event)) {
+        self.window.flush(&mut |event| {
+            callback(event);
+            ControlFlow::Continue
+        });
+    }
+
+    /// Returns the wrapped loop. The held events are dropped.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+impl<'a, L, Event> EventsLoop<Event> for Reorder<'a, L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let Reorder {
+            ref mut inner,
+            ref mut sequence,
+            ref mut window,
+        } = *self;
+        let mut deliver = |event| {
+            callback(event);
+            ControlFlow::Continue
+        };
+        window.release(&mut deliver);
+        inner.poll_events(&mut |event| {
+            let seq = sequence(&event);
+            window.accept(seq, event, &mut deliver);
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let Reorder {
+            ref mut inner,
+            ref mut sequence,
+            ref mut window,
+        } = *self;
+        if window.release(callback).is_exit() {
+            return;
+        }
+        inner.run(&mut |event| {
+            let seq = sequence(&event);
+            window.accept(seq, event, callback)
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    /// Returns `true` once the inner loop is closed and no event is held, see `flush`.
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed() && self.held() == 0
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    /// Counts the held events along with those pending in the inner loop.
+    fn pending_count(&self) -> Option<usize> {
+        self.inner
+            .pending_count()
+            .map(|pending| pending + self.held())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::Reorder;
+    use budget::MemoryBudget;
+    use mock::MockEventsLoop;
+    use EventsLoop;
+
+    #[test]
+    fn shuffled_events_come_out_in_order() {
+        let shuffled = vec![2, 1, 4, 3, 5, 8, 6, 7, 9];
+        let mut l = Reorder::new(MockEventsLoop::new(shuffled), |&n: &u64| n, 3).starting_at(1);
+
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |n| delivered.push(n));
+        assert_eq!(delivered, (1..10).collect::<Vec<_>>());
+        assert_eq!(l.held(), 0);
+    }
+
+    #[test]
+    fn gaps_and_late_arrivals_are_reported() {
+        let gaps = RefCell::new(Vec::new());
+        let late = RefCell::new(Vec::new());
+        let mut delivered = Vec::new();
+        {
+            let mut l = Reorder::new(MockEventsLoop::new(vec![1, 3, 4, 5, 2, 7]), |&n: &u64| n, 2)
+                .on_gap(|missing| gaps.borrow_mut().push(missing))
+                .on_late(|&n| late.borrow_mut().push(n));
+
+            l.poll_events(&mut |n| delivered.push(n));
+            assert_eq!(l.held(), 1);
+            assert_eq!(l.pending_count(), Some(1));
+            l.flush(&mut |n| delivered.push(n));
+        }
+        // The window of two overflows at 4, releasing 1 to start the sequence, and again at 5,
+        // giving up on 2, which then arrives late. 6 never comes, so 7 waits for the flush.
+        assert_eq!(delivered, vec![1, 3, 4, 5, 2, 7]);
+        assert_eq!(gaps.into_inner(), vec![2..3, 6..7]);
+        assert_eq!(late.into_inner(), vec![2]);
+    }
+
+    #[test]
+    fn the_budget_releases_the_oldest_events_early() {
+        let budget = MemoryBudget::new(20, |_: &u64| 10);
+        let gaps = RefCell::new(Vec::new());
+        let mut l = Reorder::new(MockEventsLoop::new(vec![3, 4, 5, 7]), |&n: &u64| n, 10)
+            .starting_at(1)
+            .on_gap(|missing| gaps.borrow_mut().push(missing))
+            .with_memory_budget(budget.clone());
+
+        // 5 doesn't fit next to 3 and 4, so 1 and 2 are given up on.
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |n| delivered.push(n));
+        assert_eq!(delivered, vec![3, 4, 5]);
+        assert_eq!(l.held(), 1);
+        assert_eq!(budget.remaining(), 10);
+
+        drop(l);
+        assert_eq!(budget.remaining(), 20);
+        assert_eq!(gaps.into_inner(), vec![1..3]);
+    }
+}
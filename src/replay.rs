@@ -0,0 +1,176 @@
+//! Replaying the latest events to late observers.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+type Observer<'a, Event> = Box<dyn FnMut(&Event) + 'a>;
+
+/// Wraps an `EventsLoop`, keeping a clone of its last `n` events so that the observers that
+/// attach late can catch up.
+///
+/// An observer given to `attach` first receives the buffered events, in their original arrival
+/// order, then every event that the loop delivers from then on, right before the callback of
+/// `poll_events` or `run` does. The buffer holds at most `n` clones: each new event evicts the
+/// oldest one once it is full, so the memory used is bounded by `n` events whatever the length
+/// of the stream.
+///
+/// Observers are attached between calls to `poll_events` and `run`, since the adapter is
+/// borrowed while it delivers events.
+pub struct ReplayBuffer<'a, L, Event> {
+    inner: L,
+    capacity: usize,
+    history: VecDeque<Event>,
+    observers: RefCell<Vec<Observer<'a, Event>>>,
+}
+
+impl<'a, L, Event> ReplayBuffer<'a, L, Event>
+where
+    L: EventsLoop<Event>,
+    Event: Clone,
+{
+    /// Builds the adapter around `inner`, keeping its last `n` events.
+    pub fn new(inner: L, n: usize) -> ReplayBuffer<'a, L, Event> {
+        ReplayBuffer {
+            inner,
+            capacity: n,
+            history: VecDeque::with_capacity(n),
+            observers: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Replays the buffered events to `observer`, then registers it for the following ones.
+    pub fn attach<F>(&self, observer: F)
+    where
+        F: FnMut(&Event) + 'a,
+    {
+        let mut observer = Box::new(observer);
+        for event in &self.history {
+            observer(event);
+        }
+        self.observers.borrow_mut().push(observer);
+    }
+
+    /// Returns the number of buffered events.
+    pub fn buffered(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Returns the wrapped loop.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+// Records `event` in the history and shows it to the observers.
+fn record<Event: Clone>(
+    history: &mut VecDeque<Event>,
+    capacity: usize,
+    observers: &mut [Observer<Event>],
+    event: &Event,
+) {
+    if capacity > 0 {
+        if history.len() == capacity {
+            history.pop_front();
+        }
+        history.push_back(event.clone());
+    }
+    for observer in observers {
+        observer(event);
+    }
+}
+
+impl<'a, L, Event> EventsLoop<Event> for ReplayBuffer<'a, L, Event>
+where
+    L: EventsLoop<Event>,
+    Event: Clone,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let capacity = self.capacity;
+        let history = &mut self.history;
+        let observers = self.observers.get_mut();
+        self.inner.poll_events(&mut |event| {
+            record(history, capacity, observers, &event);
+            callback(event);
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let capacity = self.capacity;
+        let history = &mut self.history;
+        let observers = self.observers.get_mut();
+        self.inner.run(&mut |event| {
+            record(history, capacity, observers, &event);
+            callback(event)
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::ReplayBuffer;
+    use mock::MockEventsLoop;
+    use EventsLoop;
+
+    #[test]
+    fn late_observers_receive_the_last_events_in_order() {
+        let early = RefCell::new(Vec::new());
+        let late = RefCell::new(Vec::new());
+        let mut delivered = Vec::new();
+        {
+            let mut l = ReplayBuffer::new(MockEventsLoop::new(vec![1, 2, 3, 4, 5]), 3);
+            l.attach(|&e| early.borrow_mut().push(e));
+            l.poll_events(&mut |e| delivered.push(e));
+            assert_eq!(l.buffered(), 3);
+
+            l.attach(|&e| late.borrow_mut().push(e));
+            assert_eq!(*late.borrow(), vec![3, 4, 5]);
+
+            l.inner.push(6);
+            l.poll_events(&mut |e| delivered.push(e));
+        }
+        assert_eq!(delivered, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(early.into_inner(), vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(late.into_inner(), vec![3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn zero_capacity_replays_nothing() {
+        let late = RefCell::new(Vec::new());
+        {
+            let mut l = ReplayBuffer::new(MockEventsLoop::new(vec![1, 2]), 0);
+            l.poll_events(&mut |_| ());
+            l.attach(|&e| late.borrow_mut().push(e));
+            l.inner.push(3);
+            l.poll_events(&mut |_| ());
+        }
+        assert_eq!(late.into_inner(), vec![3]);
+    }
+}
@@ -0,0 +1,226 @@
+//! Routing events to the subscribers of logical channels.
+
+use std::collections::HashMap;
+
+use {ControlFlow, EventsLoop};
+
+/// Identifies a logical channel of a `Router`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChannelId(pub u32);
+
+/// Identifies a subscription returned by `Router::subscribe`, to cancel it later.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Subscription {
+    channel: ChannelId,
+    id: u64,
+}
+
+impl Subscription {
+    /// Returns the channel the subscription listens to.
+    pub fn channel(&self) -> ChannelId {
+        self.channel
+    }
+}
+
+type Subscriber<'a, Event> = Box<dyn FnMut(&Event) -> ControlFlow + 'a>;
+
+/// Drives an `EventsLoop`, tagging each event with a channel and delivering it to the
+/// subscribers of that channel.
+///
+/// A classifier picks the channel of each event. Every subscriber of the channel sees the event,
+/// in the order they subscribed. The events of channels without subscribers go to the default
+/// sink instead. `run` returns once the event has been delivered to all of its subscribers if
+/// any of them returned `ControlFlow::Break` or `ControlFlow::ExitWithCode`.
+///
+/// Subscriptions are managed between calls to `poll_events` and `run`, since the router is
+/// borrowed while it dispatches.
+pub struct Router<'a, L, Event> {
+    inner: L,
+    classifier: Box<dyn FnMut(&Event) -> ChannelId + 'a>,
+    subscribers: HashMap<ChannelId, Vec<(u64, Subscriber<'a, Event>)>>,
+    default_sink: Box<dyn FnMut(Event) -> ControlFlow + 'a>,
+    next_id: u64,
+}
+
+impl<'a, L, Event> Router<'a, L, Event>
+where
+    L: EventsLoop<Event>,
+{
+    /// Builds a router of the events of `inner`, without any subscriber.
+    pub fn new<C, D>(inner: L, classifier: C, default_sink: D) -> Router<'a, L, Event>
+    where
+        C: FnMut(&Event) -> ChannelId + 'a,
+        D: FnMut(Event) -> ControlFlow + 'a,
+    {
+        Router {
+            inner,
+            classifier: Box::new(classifier),
+            subscribers: HashMap::new(),
+            default_sink: Box::new(default_sink),
+            next_id: 0,
+        }
+    }
+
+    /// Registers `callback` for the events of `channel`.
+    pub fn subscribe<F>(&mut self, channel: ChannelId, callback: F) -> Subscription
+    where
+        F: FnMut(&Event) -> ControlFlow + 'a,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.subscribers
+            .entry(channel)
+            .or_default()
+            .push((id, Box::new(callback)));
+        Subscription { channel, id }
+    }
+
+    /// Cancels `subscription`. Returns `false` if it was already cancelled.
+    pub fn unsubscribe(&mut self, subscription: Subscription) -> bool {
+        let subscribers = match self.subscribers.get_mut(&subscription.channel) {
+            Some(subscribers) => subscribers,
+            None => return false,
+        };
+        let before = subscribers.len();
+        subscribers.retain(|&(id, _)| id != subscription.id);
+        let removed = subscribers.len() != before;
+        if subscribers.is_empty() {
+            self.subscribers.remove(&subscription.channel);
+        }
+        removed
+    }
+
+    /// Returns the number of subscribers of `channel`.
+    pub fn subscribers(&self, channel: ChannelId) -> usize {
+        self.subscribers.get(&channel).map_or(0, Vec::len)
+    }
+
+    /// Dispatches the pending events, like `EventsLoop::poll_events`. The control flow returned
+    /// by the callbacks is ignored.
+    pub fn poll_events(&mut self) {
+        let Router {
+            ref mut inner,
+            ref mut classifier,
+            ref mut subscribers,
+            ref mut default_sink,
+            ..
+        } = *self;
+        inner.poll_events(&mut |event| {
+            dispatch(classifier, subscribers, default_sink, event);
+        });
+    }
+
+    /// Dispatches events until a callback breaks, like `EventsLoop::run`.
+    pub fn run(&mut self) {
+        let Router {
+            ref mut inner,
+            ref mut classifier,
+            ref mut subscribers,
+            ref mut default_sink,
+            ..
+        } = *self;
+        inner.run(&mut |event| dispatch(classifier, subscribers, default_sink, event));
+    }
+
+    /// Returns the wrapped loop.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+// Delivers `event` to the subscribers of its channel, or to the default sink, and returns the
+// first exit requested, if any.
+fn dispatch<'a, Event>(
+    classifier: &mut Box<dyn FnMut(&Event) -> ChannelId + 'a>,
+    subscribers: &mut HashMap<ChannelId, Vec<(u64, Subscriber<'a, Event>)>>,
+    default_sink: &mut Box<dyn FnMut(Event) -> ControlFlow + 'a>,
+    event: Event,
+) -> ControlFlow {
+    let channel = classifier(&event);
+    match subscribers.get_mut(&channel) {
+        Some(subscribers) => {
+            let mut flow = ControlFlow::Continue;
+            for &mut (_, ref mut subscriber) in subscribers.iter_mut() {
+                let result = subscriber(&event);
+                if !flow.is_exit() {
+                    flow = result;
+                }
+            }
+            flow
+        }
+        None => default_sink(event),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::{ChannelId, Router};
+    use mock::MockEventsLoop;
+    use ControlFlow;
+
+    fn by_tens(e: &u32) -> ChannelId {
+        ChannelId(e / 10)
+    }
+
+    #[test]
+    fn every_subscriber_of_a_channel_receives_its_events() {
+        let first = RefCell::new(Vec::new());
+        let second = RefCell::new(Vec::new());
+        let other = RefCell::new(Vec::new());
+        let unrouted = RefCell::new(Vec::new());
+        {
+            let mut router =
+                Router::new(MockEventsLoop::new(vec![1, 12, 2, 25, 13]), by_tens, |e| {
+                    unrouted.borrow_mut().push(e);
+                    ControlFlow::Continue
+                });
+            router.subscribe(ChannelId(0), |&e| {
+                first.borrow_mut().push(e);
+                ControlFlow::Continue
+            });
+            router.subscribe(ChannelId(0), |&e| {
+                second.borrow_mut().push(e);
+                ControlFlow::Continue
+            });
+            router.subscribe(ChannelId(1), |&e| {
+                other.borrow_mut().push(e);
+                ControlFlow::Continue
+            });
+            router.poll_events();
+        }
+        assert_eq!(first.into_inner(), vec![1, 2]);
+        assert_eq!(second.into_inner(), vec![1, 2]);
+        assert_eq!(other.into_inner(), vec![12, 13]);
+        assert_eq!(unrouted.into_inner(), vec![25]);
+    }
+
+    #[test]
+    fn unsubscribed_channels_fall_back_to_the_default_sink() {
+        let routed = RefCell::new(Vec::new());
+        let unrouted = RefCell::new(Vec::new());
+        {
+            let mut router = Router::new(MockEventsLoop::new(vec![1, 2, 3]), by_tens, |e| {
+                unrouted.borrow_mut().push(e);
+                ControlFlow::Continue
+            });
+            let subscription = router.subscribe(ChannelId(0), |&e| {
+                routed.borrow_mut().push(e);
+                if e == 2 {
+                    ControlFlow::Break
+                } else {
+                    ControlFlow::Continue
+                }
+            });
+            router.run();
+
+            assert!(router.unsubscribe(subscription));
+            assert!(!router.unsubscribe(subscription));
+            assert_eq!(router.subscribers(ChannelId(0)), 0);
+            router.poll_events();
+        }
+        assert_eq!(routed.into_inner(), vec![1, 2]);
+        assert_eq!(unrouted.into_inner(), vec![3]);
+    }
+}
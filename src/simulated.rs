@@ -0,0 +1,376 @@
+//! A deterministic `EventsLoop` driven by a virtual clock, for headless simulations and tests.
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use clock::{Clock, MockClock};
+use {ControlFlow, EventsLoop, EventsLoopClosed, EventsLoopProxy, RunningFlag};
+
+struct Entry<T> {
+    at: Instant,
+    seq: u64,
+    event: T,
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Entry<T>) -> bool {
+        self.at == other.at && self.seq == other.seq
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Entry<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Entry<T> {
+    // Reversed, so that the `BinaryHeap` pops the earliest entry first.
+    fn cmp(&self, other: &Entry<T>) -> Ordering {
+        (other.at, other.seq).cmp(&(self.at, self.seq))
+    }
+}
+
+struct Schedule<T> {
+    entries: BinaryHeap<Entry<T>>,
+    next_seq: u64,
+}
+
+/// A handle to the schedule of a `SimulatedLoop`.
+///
+/// Unlike the loop itself, the handle can be used from inside the callback, for example to
+/// schedule the follow-up of an event.
+pub struct Scheduler<T> {
+    schedule: Rc<RefCell<Schedule<T>>>,
+    clock: MockClock,
+}
+
+impl<T> Clone for Scheduler<T> {
+    fn clone(&self) -> Scheduler<T> {
+        Scheduler {
+            schedule: self.schedule.clone(),
+            clock: self.clock.clone(),
+        }
+    }
+}
+
+impl<T> Scheduler<T> {
+    /// Schedules `event` to be delivered once the virtual time reaches `at`.
+    ///
+    /// Events scheduled for the same instant are delivered in the order they were scheduled.
+    /// Events scheduled in the past are delivered as soon as possible.
+    pub fn schedule(&self, at: Instant, event: T) {
+        let mut schedule = self.schedule.borrow_mut();
+        let seq = schedule.next_seq;
+        schedule.next_seq += 1;
+        schedule.entries.push(Entry { at, seq, event });
+    }
+
+    /// Schedules `event` to be delivered `delay` after the current virtual time.
+    pub fn schedule_after(&self, delay: Duration, event: T) {
+        self.schedule(self.clock.now() + delay, event);
+    }
+
+    /// Injects `event` at the current virtual time, after the events already due.
+    pub fn inject(&self, event: T) {
+        self.schedule(self.clock.now(), event);
+    }
+
+    /// Returns the number of events that haven't been delivered yet.
+    pub fn len(&self) -> usize {
+        self.schedule.borrow().entries.len()
+    }
+
+    /// Returns `true` if every scheduled event has been delivered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn next_at(&self) -> Option<Instant> {
+        self.schedule.borrow().entries.peek().map(|entry| entry.at)
+    }
+
+    fn pop_due(&self, now: Instant) -> Option<T> {
+        let mut schedule = self.schedule.borrow_mut();
+        match schedule.entries.peek() {
+            Some(entry) if entry.at <= now => {}
+            _ => return None,
+        }
+        schedule.entries.pop().map(|entry| entry.event)
+    }
+}
+
+/// An `EventsLoop` that delivers scheduled events in timestamp order, advancing a virtual clock
+/// instead of sleeping.
+///
+/// `poll_events` delivers the events that are due at the current virtual time without moving the
+/// clock. `run` jumps the clock straight to the next scheduled event whenever nothing is due, and
+/// returns once the schedule is empty. If the callback returns `ControlFlow::WaitUntil`, the
+/// clock stops at that instant on its way to the next event, just like a real loop would wake up
+/// at the deadline.
+///
+/// Wakeups sent through the proxy are accepted and ignored, since the simulation has no system
+/// queue to interrupt and must stay deterministic.
+///
+/// The simulation reports itself closed through `EventsLoop::is_closed` once its schedule is
+/// empty and no `Scheduler` handle is left to add to it.
+pub struct SimulatedLoop<T> {
+    scheduler: Scheduler<T>,
+    running: RunningFlag,
+}
+
+impl<T> SimulatedLoop<T> {
+    /// Builds an empty simulation driven by `clock`.
+    pub fn new(clock: MockClock) -> SimulatedLoop<T> {
+        SimulatedLoop {
+            scheduler: Scheduler {
+                schedule: Rc::new(RefCell::new(Schedule {
+                    entries: BinaryHeap::new(),
+                    next_seq: 0,
+                })),
+                clock,
+            },
+            running: RunningFlag::default(),
+        }
+    }
+
+    /// Returns the virtual clock of the simulation.
+    pub fn clock(&self) -> &MockClock {
+        &self.scheduler.clock
+    }
+
+    /// Returns a handle that can schedule events from inside the callback.
+    pub fn scheduler(&self) -> Scheduler<T> {
+        self.scheduler.clone()
+    }
+
+    /// Schedules `event` for `at`. See `Scheduler::schedule`.
+    pub fn schedule(&mut self, at: Instant, event: T) {
+        self.scheduler.schedule(at, event);
+    }
+
+    /// Injects `event` at the current virtual time. See `Scheduler::inject`.
+    pub fn inject(&mut self, event: T) {
+        self.scheduler.inject(event);
+    }
+}
+
+impl<T> Extend<(Instant, T)> for SimulatedLoop<T> {
+    fn extend<I: IntoIterator<Item = (Instant, T)>>(&mut self, entries: I) {
+        for (at, event) in entries {
+            self.scheduler.schedule(at, event);
+        }
+    }
+}
+
+impl<T> EventsLoop<T> for SimulatedLoop<T> {
+    fn poll_events(&mut self, callback: &mut dyn FnMut(T)) {
+        let _running = self.running.enter();
+        let now = self.scheduler.clock.now();
+        while let Some(event) = self.scheduler.pop_due(now) {
+            callback(event);
+        }
+    }
+
+    /// Lets `wait` move the virtual time when nothing is due. `wait` receives the time left
+    /// until the next scheduled event, or `None` if the schedule is empty.
+    fn poll_events_with_waiter(
+        &mut self,
+        wait: &mut dyn FnMut(Option<Duration>) -> bool,
+        callback: &mut dyn FnMut(T),
+    ) {
+        let _running = self.running.enter();
+        loop {
+            let now = self.scheduler.clock.now();
+            let mut delivered = false;
+            while let Some(event) = self.scheduler.pop_due(now) {
+                delivered = true;
+                callback(event);
+            }
+            if delivered {
+                return;
+            }
+            let timeout = self.scheduler.next_at().map(|at| at - now);
+            if !wait(timeout) {
+                return;
+            }
+        }
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(T) -> ControlFlow) {
+        let _running = self.running.enter();
+        let clock = &self.scheduler.clock;
+        let mut wake_at = None;
+
+        loop {
+            let next_at = match self.scheduler.next_at() {
+                Some(at) => at,
+                None => {
+                    if let Some(deadline) = wake_at {
+                        clock.advance_to(deadline);
+                    }
+                    return;
+                }
+            };
+
+            if let Some(deadline) = wake_at.take() {
+                if deadline < next_at {
+                    clock.advance_to(deadline);
+                }
+            }
+            clock.advance_to(next_at);
+
+            let event = match self.scheduler.pop_due(clock.now()) {
+                Some(event) => event,
+                None => continue,
+            };
+            match callback(event) {
+                ControlFlow::Continue => {}
+                ControlFlow::WaitUntil(deadline) => wake_at = Some(deadline),
+                ControlFlow::Break | ControlFlow::ExitWithCode(_) => return,
+            }
+        }
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(SimulatedProxy)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.scheduler.is_empty() && Rc::strong_count(&self.scheduler.schedule) == 1
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.get()
+    }
+}
+
+struct SimulatedProxy;
+
+impl EventsLoopProxy for SimulatedProxy {
+    fn wakeup(&self) -> Result<(), EventsLoopClosed> {
+        Ok(())
+    }
+
+    fn clone(&self) -> Box<dyn EventsLoopProxy> {
+        Box::new(SimulatedProxy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SimulatedLoop;
+    use clock::{Clock, MockClock};
+    use {ControlFlow, EventsLoop};
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn delivers_in_timestamp_order() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut sim = SimulatedLoop::new(clock.clone());
+        sim.extend(vec![(start + ms(30), "c"), (start + ms(10), "a"), (start + ms(20), "b")]);
+        sim.schedule(start + ms(10), "a2");
+        sim.inject("now");
+
+        let scheduler = sim.scheduler();
+        let mut delivered = Vec::new();
+        sim.run(&mut |event| {
+            delivered.push((event, clock.now() - start));
+            if event == "a" {
+                scheduler.schedule_after(ms(15), "follow-up");
+            }
+            ControlFlow::Continue
+        });
+
+        assert_eq!(
+            delivered,
+            vec![
+                ("now", ms(0)),
+                ("a", ms(10)),
+                ("a2", ms(10)),
+                ("b", ms(20)),
+                ("follow-up", ms(25)),
+                ("c", ms(30)),
+            ]
+        );
+    }
+
+    #[test]
+    fn poll_delivers_only_due_events() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut sim = SimulatedLoop::new(clock.clone());
+        sim.extend(vec![(start, 1), (start + ms(5), 2)]);
+
+        let mut delivered = Vec::new();
+        sim.poll_events(&mut |event| delivered.push(event));
+        assert_eq!(delivered, vec![1]);
+
+        clock.advance(ms(5));
+        sim.poll_events(&mut |event| delivered.push(event));
+        assert_eq!(delivered, vec![1, 2]);
+    }
+
+    #[test]
+    fn waiter_moves_the_virtual_time() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut sim = SimulatedLoop::new(clock.clone());
+        sim.schedule(start + ms(15), "late");
+
+        let mut timeouts = Vec::new();
+        let mut delivered = Vec::new();
+        sim.poll_events_with_waiter(
+            &mut |timeout| {
+                timeouts.push(timeout);
+                clock.advance(ms(10));
+                true
+            },
+            &mut |event| delivered.push((event, clock.now() - start)),
+        );
+        assert_eq!(timeouts, vec![Some(ms(15)), Some(ms(5))]);
+        assert_eq!(delivered, vec![("late", ms(20))]);
+
+        sim.poll_events_with_waiter(
+            &mut |timeout| {
+                assert_eq!(timeout, None);
+                false
+            },
+            &mut |_| panic!("nothing is scheduled"),
+        );
+    }
+
+    #[test]
+    fn wait_until_stops_the_clock_at_the_deadline() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut sim = SimulatedLoop::new(clock.clone());
+        sim.schedule(start + ms(1), ());
+
+        sim.run(&mut |()| ControlFlow::WaitUntil(start + ms(40)));
+        assert_eq!(clock.now(), start + ms(40));
+    }
+
+    #[test]
+    fn closes_once_nothing_can_be_scheduled() {
+        let mut sim = SimulatedLoop::new(MockClock::new());
+        sim.inject(1);
+        let scheduler = sim.scheduler();
+        sim.poll_events(&mut |_| ());
+        assert!(!sim.is_closed());
+
+        drop(scheduler);
+        assert!(sim.is_closed());
+    }
+}
@@ -0,0 +1,143 @@
+//! Guarding against several pipelines consuming the events of the same loop.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+/// A handle to an `EventsLoop` that checks, in debug builds, that its events are consumed
+/// through one handle only.
+///
+/// Cloning a `SingleConsumer` gives another handle to the same loop, for example to wrap it in
+/// several adapters. The first handle that calls `poll_events` or `run` becomes the consumer of
+/// the loop, and any other handle doing so afterwards panics. This catches a class of bugs where
+/// two pipelines drain the same loop and each ends up with an arbitrary subset of its events.
+/// Creating proxies doesn't count as consuming.
+///
+/// The guard only knows of the handles cloned from the same `SingleConsumer`. If the wrapped
+/// loop is itself a handle to a shared loop, the other handles of that loop, including a second
+/// `SingleConsumer` built around one of them, can still consume it without being detected. Wrap
+/// the loop itself, and reach it through clones of that one `SingleConsumer` only.
+///
+/// The loop is neither `Send` nor `Sync`, so this is purely a logic guard. The check is only
+/// performed when debug assertions are enabled: in release builds the handles simply share the
+/// loop.
+pub struct SingleConsumer<L> {
+    inner: Rc<RefCell<L>>,
+    consumer: Rc<Cell<usize>>,
+    handles: Rc<Cell<usize>>,
+    id: usize,
+}
+
+impl<L> SingleConsumer<L> {
+    /// Wraps `inner`, returning its first handle.
+    pub fn new(inner: L) -> SingleConsumer<L> {
+        SingleConsumer {
+            inner: Rc::new(RefCell::new(inner)),
+            consumer: Rc::new(Cell::new(0)),
+            handles: Rc::new(Cell::new(1)),
+            id: 1,
+        }
+    }
+
+    /// Returns `true` if this handle is the one that consumes the events of the loop.
+    ///
+    /// Always returns `false` in release builds, where consumption isn't tracked.
+    pub fn is_consumer(&self) -> bool {
+        self.consumer.get() == self.id
+    }
+
+    #[cfg(debug_assertions)]
+    fn claim(&self) {
+        match self.consumer.get() {
+            0 => self.consumer.set(self.id),
+            id if id == self.id => {}
+            _ => panic!("the events of this loop are already consumed through another handle"),
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn claim(&self) {}
+}
+
+impl<L> Clone for SingleConsumer<L> {
+    fn clone(&self) -> SingleConsumer<L> {
+        let id = self.handles.get() + 1;
+        self.handles.set(id);
+        SingleConsumer {
+            inner: self.inner.clone(),
+            consumer: self.consumer.clone(),
+            handles: self.handles.clone(),
+            id,
+        }
+    }
+}
+
+impl<L, Event> EventsLoop<Event> for SingleConsumer<L>
+where
+    L: EventsLoop<Event>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        self.claim();
+        self.inner.borrow_mut().poll_events(callback);
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        self.claim();
+        self.inner.borrow_mut().run(callback);
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.borrow().create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.borrow().create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.borrow().remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.borrow().is_closed()
+    }
+
+    // The loop is borrowed for as long as one of the handles is running it.
+    fn is_running(&self) -> bool {
+        self.inner
+            .try_borrow()
+            .map_or(true, |inner| inner.is_running())
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.borrow().pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SingleConsumer;
+    use mock::MockEventsLoop;
+    use EventsLoop;
+
+    #[test]
+    fn the_consumer_can_poll_repeatedly() {
+        let mut l = SingleConsumer::new(MockEventsLoop::new(vec![1, 2]));
+        let other = l.clone();
+        l.poll_events(&mut |_| ());
+        l.poll_events(&mut |_| ());
+        assert_eq!(l.is_consumer(), cfg!(debug_assertions));
+        assert!(!other.is_consumer());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "already consumed")]
+    fn a_second_consumer_panics() {
+        let mut first = SingleConsumer::new(MockEventsLoop::new(vec![1, 2]));
+        let mut second = first.clone();
+        first.poll_events(&mut |_| ());
+        second.poll_events(&mut |_| ());
+    }
+}
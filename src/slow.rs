@@ -0,0 +1,162 @@
+//! Detecting the callbacks that take too long.
+
+use std::time::{Duration, Instant};
+
+use clock::{Clock, SystemClock};
+use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+/// Wraps an `EventsLoop` and times every invocation of the callback, reporting those that take
+/// longer than a threshold.
+///
+/// As the docs of `EventsLoop::run` warn, a slow callback keeps the loop from emptying its
+/// queue. This adapter makes such callbacks visible: whenever one runs for longer than the
+/// threshold, `on_slow` is called with the time it took, right after it returns, for example to
+/// log it. The events and the control flow are forwarded unchanged.
+pub struct SlowCallbackWarn<'a, L, C = SystemClock> {
+    inner: L,
+    clock: C,
+    threshold: Duration,
+    on_slow: Box<dyn FnMut(Duration) + 'a>,
+}
+
+impl<'a, L> SlowCallbackWarn<'a, L> {
+    /// Builds the adapter around `inner`, calling `on_slow` for the callbacks that take longer
+    /// than `threshold`.
+    pub fn new<F>(inner: L, threshold: Duration, on_slow: F) -> SlowCallbackWarn<'a, L>
+    where
+        F: FnMut(Duration) + 'a,
+    {
+        SlowCallbackWarn {
+            inner,
+            clock: SystemClock,
+            threshold,
+            on_slow: Box::new(on_slow),
+        }
+    }
+}
+
+impl<'a, L, C> SlowCallbackWarn<'a, L, C> {
+    /// Replaces the clock used to time the callbacks.
+    pub fn with_clock<C2: Clock>(self, clock: C2) -> SlowCallbackWarn<'a, L, C2> {
+        SlowCallbackWarn {
+            inner: self.inner,
+            clock,
+            threshold: self.threshold,
+            on_slow: self.on_slow,
+        }
+    }
+
+    /// Returns the wrapped loop.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+// Reports the callback started at `start` to `on_slow` if it took longer than `threshold`.
+fn check<C: Clock>(
+    clock: &C,
+    start: Instant,
+    threshold: Duration,
+    on_slow: &mut dyn FnMut(Duration),
+) {
+    let elapsed = clock.now() - start;
+    if elapsed > threshold {
+        on_slow(elapsed);
+    }
+}
+
+impl<'a, L, C, Event> EventsLoop<Event> for SlowCallbackWarn<'a, L, C>
+where
+    L: EventsLoop<Event>,
+    C: Clock,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let SlowCallbackWarn {
+            ref mut inner,
+            ref clock,
+            threshold,
+            ref mut on_slow,
+        } = *self;
+        inner.poll_events(&mut |event| {
+            let start = clock.now();
+            callback(event);
+            check(clock, start, threshold, &mut **on_slow);
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let SlowCallbackWarn {
+            ref mut inner,
+            ref clock,
+            threshold,
+            ref mut on_slow,
+        } = *self;
+        inner.run(&mut |event| {
+            let start = clock.now();
+            let flow = callback(event);
+            check(clock, start, threshold, &mut **on_slow);
+            flow
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::SlowCallbackWarn;
+    use clock::MockClock;
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop};
+
+    #[test]
+    fn reports_the_callbacks_over_the_threshold() {
+        let clock = MockClock::new();
+        let mut slow = Vec::new();
+        {
+            let mut l = SlowCallbackWarn::new(
+                MockEventsLoop::new(vec![1, 20, 3, 50]),
+                Duration::from_millis(10),
+                |elapsed| slow.push(elapsed),
+            )
+            .with_clock(clock.clone());
+
+            // Each event takes as many milliseconds as its value.
+            let mut delivered = Vec::new();
+            l.run(&mut |ms| {
+                clock.advance(Duration::from_millis(ms));
+                delivered.push(ms);
+                ControlFlow::Continue
+            });
+            assert_eq!(delivered, vec![1, 20, 3, 50]);
+        }
+        assert_eq!(
+            slow,
+            vec![Duration::from_millis(20), Duration::from_millis(50)]
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Forwarding a subset of the events out of the primary stream.
+
+use std::sync::mpsc::Sender;
+
+use inject::Injector;
+use {ControlFlow, DropSink, EventsLoop, EventsLoopProxy};
+
+/// A destination for the events forwarded by `Split`.
+pub trait EventSink<Event> {
+    /// Hands `event` over to the sink, or gives it back if the sink can't take it anymore.
+    fn send_event(&mut self, event: Event) -> Result<(), Event>;
+}
+
+/// Queues the events into the injector, which never refuses any.
+impl<Event> EventSink<Event> for Injector<Event> {
+    fn send_event(&mut self, event: Event) -> Result<(), Event> {
+        self.push(event);
+        Ok(())
+    }
+}
+
+/// Sends the events through the channel, giving them back once the receiver is gone.
+impl<Event> EventSink<Event> for Sender<Event> {
+    fn send_event(&mut self, event: Event) -> Result<(), Event> {
+        self.send(event).map_err(|err| err.0)
+    }
+}
+
+/// Wraps an `EventsLoop` and forwards the events matching a predicate to a secondary
+/// `EventSink`, such as an `Injector` or a `Sender` feeding another loop.
+///
+/// The forwarded events leave the primary stream entirely: the callback of `poll_events` and
+/// `run` only sees the others. Both streams keep the relative order of their events, and each
+/// event is forwarded at the point where it would have been delivered. If the sink gives an
+/// event back, the event is reported to the `DropSink` given to `with_drop_sink`, if any, and is
+/// not delivered to the primary callback either.
+pub struct Split<'a, L, Event, S> {
+    inner: L,
+    predicate: Box<dyn FnMut(&Event) -> bool + 'a>,
+    sink: S,
+    drop_sink: Option<DropSink<Event>>,
+}
+
+impl<'a, L, Event, S> Split<'a, L, Event, S>
+where
+    L: EventsLoop<Event>,
+    S: EventSink<Event>,
+{
+    /// Builds the adapter around `inner`, forwarding the events matching `predicate` to `sink`.
+    pub fn new<P>(inner: L, predicate: P, sink: S) -> Split<'a, L, Event, S>
+    where
+        P: FnMut(&Event) -> bool + 'a,
+    {
+        Split {
+            inner,
+            predicate: Box::new(predicate),
+            sink,
+            drop_sink: None,
+        }
+    }
+
+    /// Reports the events that the sink refused to `sink`.
+    pub fn with_drop_sink(mut self, sink: DropSink<Event>) -> Split<'a, L, Event, S> {
+        self.drop_sink = Some(sink);
+        self
+    }
+
+    /// Returns the secondary sink.
+    pub fn sink(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Returns the wrapped loop and the secondary sink.
+    pub fn into_inner(self) -> (L, S) {
+        (self.inner, self.sink)
+    }
+}
+
+// Forwards `event` to the sink if it matches, and returns it otherwise.
+fn forward<'a, Event, S: EventSink<Event>>(
+    predicate: &mut Box<dyn FnMut(&Event) -> bool + 'a>,
+    sink: &mut S,
+    drop_sink: &Option<DropSink<Event>>,
+    event: Event,
+) -> Option<Event> {
+    if !predicate(&event) {
+        return Some(event);
+    }
+    if let Err(event) = sink.send_event(event) {
+        if let Some(ref drop_sink) = *drop_sink {
+            (*drop_sink.borrow_mut())(event);
+        }
+    }
+    None
+}
+
+impl<'a, L, Event, S> EventsLoop<Event> for Split<'a, L, Event, S>
+where
+    L: EventsLoop<Event>,
+    S: EventSink<Event>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let Split {
+            ref mut inner,
+            ref mut predicate,
+            ref mut sink,
+            ref drop_sink,
+        } = *self;
+        inner.poll_events(&mut |event| {
+            if let Some(event) = forward(predicate, sink, drop_sink, event) {
+                callback(event);
+            }
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let Split {
+            ref mut inner,
+            ref mut predicate,
+            ref mut sink,
+            ref drop_sink,
+        } = *self;
+        inner.run(
+            &mut |event| match forward(predicate, sink, drop_sink, event) {
+                Some(event) => callback(event),
+                None => ControlFlow::Continue,
+            },
+        );
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+
+    use super::Split;
+    use inject::Injector;
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop};
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum Input {
+        Key(char),
+        Mouse(i32),
+    }
+
+    fn is_key(input: &Input) -> bool {
+        match *input {
+            Input::Key(_) => true,
+            Input::Mouse(_) => false,
+        }
+    }
+
+    #[test]
+    fn matching_events_go_to_the_secondary_sink() {
+        let inputs = vec![
+            Input::Key('a'),
+            Input::Mouse(1),
+            Input::Key('b'),
+            Input::Mouse(2),
+        ];
+        let pane = Injector::new();
+        let mut l = Split::new(MockEventsLoop::new(inputs), is_key, pane.clone());
+
+        let mut main = Vec::new();
+        l.run(&mut |input| {
+            main.push(input);
+            ControlFlow::Continue
+        });
+        assert_eq!(main, vec![Input::Mouse(1), Input::Mouse(2)]);
+
+        let mut focused = MockEventsLoop::new(Vec::new());
+        while let Some(input) = pane.pop() {
+            focused.push(input);
+        }
+        let mut keys = Vec::new();
+        focused.poll_events(&mut |input| keys.push(input));
+        assert_eq!(keys, vec![Input::Key('a'), Input::Key('b')]);
+    }
+
+    #[test]
+    fn refused_events_are_reported() {
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let reported = dropped.clone();
+        let mut l = Split::new(
+            MockEventsLoop::new(vec![Input::Key('a'), Input::Mouse(1)]),
+            is_key,
+            sender,
+        )
+        .with_drop_sink(Rc::new(RefCell::new(move |e| {
+            reported.borrow_mut().push(e)
+        })));
+
+        let mut main = Vec::new();
+        l.poll_events(&mut |input| main.push(input));
+        assert_eq!(main, vec![Input::Mouse(1)]);
+        assert_eq!(*dropped.borrow(), vec![Input::Key('a')]);
+    }
+}
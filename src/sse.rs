@@ -0,0 +1,318 @@
+//! Writing the events of a loop as a feed of Server-Sent Events.
+
+use std::io::{self, Write};
+use std::iter;
+
+use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+type EncodeFn<'a, Event> = Box<dyn FnMut(&Event) -> String + 'a>;
+
+/// Wraps an `EventsLoop` and writes every event to a `Write`r as a Server-Sent Events frame,
+/// before forwarding it unchanged to the callback.
+///
+/// The payload of the frame is produced by the encoder given to `new`, typically a JSON
+/// serializer: the crate has no serialization dependency, so the encoding is left to the
+/// application. The payload is written as `data: <payload>`, split into one `data:` line per
+/// line of the payload as the format requires, and followed by an empty line. An empty payload
+/// still gets its `data:` line, without which clients would drop the frame. With
+/// `with_event_type`, every frame also starts with an `event: <type>` line, for example with the
+/// name of the variant, so that clients can listen per type: the line breaks of the type are
+/// replaced with `_`, so that it can't spill into the frame. The writer is flushed after every
+/// frame, so that clients receive the events promptly.
+///
+/// If writing fails, `run` breaks right away, without forwarding the event whose frame was lost.
+/// `poll_events` can't break, so it stops writing and keeps forwarding the events. Either way no
+/// frame is written after an error, which `take_error` returns.
+pub struct SseSink<'a, L, W, Event> {
+    inner: L,
+    writer: W,
+    encode: EncodeFn<'a, Event>,
+    event_type: Option<EncodeFn<'a, Event>>,
+    error: Option<io::Error>,
+}
+
+impl<'a, L, W, Event> SseSink<'a, L, W, Event>
+where
+    L: EventsLoop<Event>,
+    W: Write,
+{
+    /// Builds the adapter around `inner`, writing the frames to `writer` with the payloads
+    /// produced by `encode`.
+    pub fn new<F>(inner: L, writer: W, encode: F) -> SseSink<'a, L, W, Event>
+    where
+        F: FnMut(&Event) -> String + 'a,
+    {
+        SseSink {
+            inner,
+            writer,
+            encode: Box::new(encode),
+            event_type: None,
+            error: None,
+        }
+    }
+
+    /// Starts every frame with an `event:` line holding the type computed by `event_type`.
+    pub fn with_event_type<F>(mut self, event_type: F) -> SseSink<'a, L, W, Event>
+    where
+        F: FnMut(&Event) -> String + 'a,
+    {
+        self.event_type = Some(Box::new(event_type));
+        self
+    }
+
+    /// Returns the error that stopped the writing, if any, and starts writing again.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    /// Returns the wrapped loop and the writer.
+    pub fn into_inner(self) -> (L, W) {
+        (self.inner, self.writer)
+    }
+}
+
+// Writes the frame of `event`, unless a previous write failed. Returns `false` if the writer is
+// in error.
+fn write_frame<W: Write, Event>(
+    writer: &mut W,
+    encode: &mut dyn FnMut(&Event) -> String,
+    event_type: &mut Option<EncodeFn<Event>>,
+    error: &mut Option<io::Error>,
+    event: &Event,
+) -> bool {
+    if error.is_some() {
+        return false;
+    }
+    let mut frame = String::new();
+    if let Some(ref mut event_type) = *event_type {
+        frame.push_str("event: ");
+        frame.push_str(&sanitize(&event_type(event)));
+        frame.push('\n');
+    }
+    // A lone `\r` ends a line too in the format.
+    let payload = encode(event).replace("\r\n", "\n").replace('\r', "\n");
+    let mut lines = payload.lines();
+    let first = lines.next().unwrap_or("");
+    for line in iter::once(first).chain(lines) {
+        frame.push_str("data: ");
+        frame.push_str(line);
+        frame.push('\n');
+    }
+    frame.push('\n');
+    match writer
+        .write_all(frame.as_bytes())
+        .and_then(|()| writer.flush())
+    {
+        Ok(()) => true,
+        Err(err) => {
+            *error = Some(err);
+            false
+        }
+    }
+}
+
+// Replaces the line breaks of an event type, which would end the `event:` line early.
+fn sanitize(event_type: &str) -> String {
+    event_type.replace(['\n', '\r'], "_")
+}
+
+impl<'a, L, W, Event> EventsLoop<Event> for SseSink<'a, L, W, Event>
+where
+    L: EventsLoop<Event>,
+    W: Write,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let SseSink {
+            ref mut inner,
+            ref mut writer,
+            ref mut encode,
+            ref mut event_type,
+            ref mut error,
+        } = *self;
+        inner.poll_events(&mut |event| {
+            write_frame(writer, &mut **encode, event_type, error, &event);
+            callback(event);
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let SseSink {
+            ref mut inner,
+            ref mut writer,
+            ref mut encode,
+            ref mut event_type,
+            ref mut error,
+        } = *self;
+        if error.is_some() {
+            return;
+        }
+        inner.run(&mut |event| {
+            if !write_frame(writer, &mut **encode, event_type, error, &event) {
+                return ControlFlow::Break;
+            }
+            callback(event)
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use super::SseSink;
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop};
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Event {
+        Key(char),
+        Moved(i32, i32),
+    }
+
+    fn encode(event: &Event) -> String {
+        match *event {
+            Event::Key(c) => format!("{{\"key\":\"{}\"}}", c),
+            Event::Moved(x, y) => format!("{{\"x\":{},\n\"y\":{}}}", x, y),
+        }
+    }
+
+    fn variant(event: &Event) -> String {
+        match *event {
+            Event::Key(_) => "Key".to_owned(),
+            Event::Moved(..) => "Moved".to_owned(),
+        }
+    }
+
+    // Parses an SSE stream back into the events it carries.
+    fn parse(stream: &str) -> Vec<Event> {
+        stream
+            .split_terminator("\n\n")
+            .map(|frame| {
+                let mut kind = "";
+                let mut data = Vec::new();
+                for line in frame.lines() {
+                    if let Some(value) = line.strip_prefix("event: ") {
+                        kind = value;
+                    } else if let Some(value) = line.strip_prefix("data: ") {
+                        data.push(value);
+                    }
+                }
+                let data = data.join("");
+                let numbers: Vec<i32> = data
+                    .split(|c: char| !c.is_ascii_digit() && c != '-')
+                    .filter_map(|n| n.parse().ok())
+                    .collect();
+                match kind {
+                    "Key" => Event::Key(data.chars().nth(8).unwrap()),
+                    "Moved" => Event::Moved(numbers[0], numbers[1]),
+                    _ => panic!("unknown event type {:?}", kind),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frames_parse_back_into_the_events() {
+        let events = vec![Event::Key('a'), Event::Moved(3, -4), Event::Key('b')];
+        let mut l = SseSink::new(MockEventsLoop::new(events.clone()), Vec::new(), encode)
+            .with_event_type(variant);
+
+        let mut delivered = Vec::new();
+        l.run(&mut |e| {
+            delivered.push(e);
+            ControlFlow::Continue
+        });
+        assert_eq!(delivered, events);
+
+        let (_, written) = l.into_inner();
+        let stream = String::from_utf8(written).unwrap();
+        assert!(stream.starts_with("event: Key\ndata: {\"key\":\"a\"}\n\n"));
+        assert_eq!(parse(&stream), events);
+    }
+
+    #[test]
+    fn empty_payloads_keep_their_data_line() {
+        let mut l = SseSink::new(
+            MockEventsLoop::new(vec![Event::Key('a')]),
+            Vec::new(),
+            |_| String::new(),
+        );
+        l.poll_events(&mut |_| {});
+
+        let (_, written) = l.into_inner();
+        assert_eq!(String::from_utf8(written).unwrap(), "data: \n\n");
+    }
+
+    #[test]
+    fn line_breaks_dont_escape_their_line() {
+        let mut l = SseSink::new(
+            MockEventsLoop::new(vec![Event::Key('a')]),
+            Vec::new(),
+            |_| "a\rb".to_owned(),
+        )
+        .with_event_type(|_| "Key\r\ndata: forged".to_owned());
+        l.poll_events(&mut |_| {});
+
+        let (_, written) = l.into_inner();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "event: Key__data: forged\ndata: a\ndata: b\n\n"
+        );
+    }
+
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "client gone"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_errors_break_the_loop() {
+        let mut l = SseSink::new(
+            MockEventsLoop::new(vec![Event::Key('a'), Event::Key('b')]),
+            Broken,
+            encode,
+        );
+        let mut delivered = 0;
+        l.run(&mut |_| {
+            delivered += 1;
+            ControlFlow::Continue
+        });
+        assert_eq!(delivered, 0);
+        assert_eq!(l.pending_count(), Some(1));
+        assert_eq!(
+            l.take_error().map(|err| err.kind()),
+            Some(io::ErrorKind::BrokenPipe)
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Expanding events into any number of outputs, based on accumulated state.
+
+use std::collections::VecDeque;
+
+use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+type Step<'a, S, Event, U> = Box<dyn FnMut(&mut S, Event, &mut VecDeque<U>) + 'a>;
+
+/// Wraps an `EventsLoop` and turns each of its events into zero or more output events, with the
+/// help of a state that persists across events.
+///
+/// The step function receives the state and an input event, and returns the outputs for that
+/// event, possibly none, as anything that can be iterated over. This lets it accumulate raw
+/// events in the state, suppress them, and emit a higher-level event once a pattern completes.
+///
+/// The outputs of an input are delivered one after the other. If the callback of `run` breaks
+/// in the middle of a sequence, the remaining outputs are kept, and delivered first by the next
+/// call to `poll_events` or `run`, before any new input is taken from the inner loop. No output
+/// is lost between calls.
+pub struct StatefulMap<'a, L, S, Event, U> {
+    inner: L,
+    state: S,
+    step: Step<'a, S, Event, U>,
+    pending: VecDeque<U>,
+}
+
+impl<'a, L, S, Event, U> StatefulMap<'a, L, S, Event, U>
+where
+    L: EventsLoop<Event>,
+{
+    /// Builds the adapter around `inner`, starting from `initial` state.
+    pub fn new<F, I>(inner: L, initial: S, mut step: F) -> StatefulMap<'a, L, S, Event, U>
+    where
+        F: FnMut(&mut S, Event) -> I + 'a,
+        I: IntoIterator<Item = U>,
+    {
+        StatefulMap {
+            inner,
+            state: initial,
+            step: Box::new(move |state, event, pending| pending.extend(step(state, event))),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Returns the current state.
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns the number of outputs waiting to be delivered.
+    pub fn buffered(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the wrapped loop and the final state. The outputs that weren't delivered are
+    /// dropped.
+    pub fn into_inner(self) -> (L, S) {
+        (self.inner, self.state)
+    }
+}
+
+// Delivers the pending outputs until the callback exits, and returns the last flow.
+fn flush<U>(pending: &mut VecDeque<U>, callback: &mut dyn FnMut(U) -> ControlFlow) -> ControlFlow {
+    let mut flow = ControlFlow::Continue;
+    while !flow.is_exit() {
+        match pending.pop_front() {
+            Some(output) => flow = callback(output),
+            None => break,
+        }
+    }
+    flow
+}
+
+impl<'a, L, S, Event, U> EventsLoop<U> for StatefulMap<'a, L, S, Event, U>
+where
+    L: EventsLoop<Event>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(U)) {
+        let StatefulMap {
+            ref mut inner,
+            ref mut state,
+            ref mut step,
+            ref mut pending,
+        } = *self;
+        let mut deliver = |output| {
+            callback(output);
+            ControlFlow::Continue
+        };
+        flush(pending, &mut deliver);
+        inner.poll_events(&mut |event| {
+            step(state, event, pending);
+            flush(pending, &mut deliver);
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(U) -> ControlFlow) {
+        let StatefulMap {
+            ref mut inner,
+            ref mut state,
+            ref mut step,
+            ref mut pending,
+        } = *self;
+        if flush(pending, callback).is_exit() {
+            return;
+        }
+        inner.run(&mut |event| {
+            step(state, event, pending);
+            flush(pending, callback)
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    /// Returns `true` once the inner loop is closed and every output has been delivered.
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed() && self.pending.is_empty()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    /// Counts the outputs waiting to be delivered along with the events pending in the inner
+    /// loop.
+    fn pending_count(&self) -> Option<usize> {
+        self.inner
+            .pending_count()
+            .map(|pending| pending + self.pending.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatefulMap;
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop};
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum Pointer {
+        Down(i32),
+        Up(i32),
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Gesture {
+        Click(i32),
+        Drag(i32, i32),
+    }
+
+    #[test]
+    fn recognizes_a_two_event_pattern() {
+        let raw = vec![
+            Pointer::Down(1),
+            Pointer::Up(1),
+            Pointer::Down(5),
+            Pointer::Up(9),
+            Pointer::Up(3),
+        ];
+        let mut l = StatefulMap::new(MockEventsLoop::new(raw), None, |down, event| {
+            match (down.take(), event) {
+                (_, Pointer::Down(at)) => {
+                    *down = Some(at);
+                    None
+                }
+                (Some(from), Pointer::Up(to)) if from == to => Some(Gesture::Click(to)),
+                (Some(from), Pointer::Up(to)) => Some(Gesture::Drag(from, to)),
+                (None, Pointer::Up(_)) => None,
+            }
+        });
+
+        let mut gestures = Vec::new();
+        l.poll_events(&mut |g| gestures.push(g));
+        assert_eq!(gestures, vec![Gesture::Click(1), Gesture::Drag(5, 9)]);
+        assert_eq!(*l.state(), None);
+    }
+
+    #[test]
+    fn outputs_left_by_a_break_are_delivered_next() {
+        let mut l = StatefulMap::new(MockEventsLoop::new(vec![3, 1]), 0, |total, n| {
+            *total += n;
+            (0..n).collect::<Vec<_>>()
+        });
+
+        let mut delivered = Vec::new();
+        l.run(&mut |n| {
+            delivered.push(n);
+            ControlFlow::Break
+        });
+        assert_eq!(delivered, vec![0]);
+        assert_eq!(l.buffered(), 2);
+
+        l.poll_events(&mut |n| delivered.push(n));
+        assert_eq!(delivered, vec![0, 1, 2, 0]);
+        assert_eq!(*l.state(), 4);
+    }
+
+    // Expands every event into itself and two followers.
+    fn expanding(events: Vec<u32>) -> StatefulMap<'static, MockEventsLoop<u32>, (), u32, u32> {
+        StatefulMap::new(MockEventsLoop::new(events), (), |_, n| {
+            vec![n, n + 10, n + 20]
+        })
+    }
+
+    fn break_on_first(l: &mut StatefulMap<'static, MockEventsLoop<u32>, (), u32, u32>) {
+        l.run(&mut |_| ControlFlow::Break);
+    }
+
+    #[test]
+    fn pending_outputs_are_counted() {
+        let mut l = expanding(vec![1, 2]);
+        break_on_first(&mut l);
+        assert_eq!(l.pending_count(), Some(3));
+    }
+
+    #[test]
+    fn stays_open_until_the_outputs_are_delivered() {
+        let mut l = expanding(vec![1]);
+        break_on_first(&mut l);
+        assert!(!l.is_closed());
+
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |n| delivered.push(n));
+        assert_eq!(delivered, vec![11, 21]);
+        assert!(l.is_closed());
+    }
+}
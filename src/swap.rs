@@ -0,0 +1,138 @@
+//! Replacing the callback of a running loop.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use {ControlFlow, EventsLoopClosed};
+
+/// A boxed callback that can be sent to the thread of the loop.
+pub type BoxedCallback<Event> = Box<dyn FnMut(Event) -> ControlFlow + Send>;
+
+/// Holds the callback given to `EventsLoop::run_swappable`, and the replacements sent to it
+/// through its `SwapHandle`s.
+///
+/// The replacements are applied between events, right before the next event is delivered, and
+/// never while a callback runs. When several replacements arrive in between two events, only the
+/// last one is kept. The replaced callbacks are dropped on the thread of the loop at that point.
+///
+/// The handles can be used from anywhere, including from inside the current callback, in which
+/// case the new callback takes over from the next event. Swapping doesn't wake up the loop: a
+/// blocked `run` picks up the new callback when the next event arrives.
+pub struct CallbackCell<Event> {
+    current: BoxedCallback<Event>,
+    sender: Sender<BoxedCallback<Event>>,
+    receiver: Receiver<BoxedCallback<Event>>,
+}
+
+impl<Event> CallbackCell<Event> {
+    /// Builds the cell, starting with `initial`.
+    pub fn new<F>(initial: F) -> CallbackCell<Event>
+    where
+        F: FnMut(Event) -> ControlFlow + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        CallbackCell {
+            current: Box::new(initial),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Returns a handle that can replace the callback from any thread.
+    pub fn handle(&self) -> SwapHandle<Event> {
+        SwapHandle {
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Applies the pending replacement, if any, then delivers `event` to the current callback.
+    pub fn call(&mut self, event: Event) -> ControlFlow {
+        if let Some(callback) = self.receiver.try_iter().last() {
+            self.current = callback;
+        }
+        (self.current)(event)
+    }
+}
+
+/// Replaces the callback of a `CallbackCell`, from any thread.
+pub struct SwapHandle<Event> {
+    sender: Sender<BoxedCallback<Event>>,
+}
+
+impl<Event> Clone for SwapHandle<Event> {
+    fn clone(&self) -> SwapHandle<Event> {
+        SwapHandle {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<Event> SwapHandle<Event> {
+    /// Replaces the callback, starting from the next event.
+    ///
+    /// Returns an `Err` if the `CallbackCell` no longer exists.
+    pub fn swap<F>(&self, callback: F) -> Result<(), EventsLoopClosed>
+    where
+        F: FnMut(Event) -> ControlFlow + Send + 'static,
+    {
+        self.sender
+            .send(Box::new(callback))
+            .map_err(|_| EventsLoopClosed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::{CallbackCell, SwapHandle};
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop};
+
+    #[test]
+    fn swap_from_inside_the_callback_takes_effect_from_the_next_event() {
+        let (sender, receiver) = mpsc::channel();
+        let (handle_sender, handle_receiver) = mpsc::channel();
+        let first = sender.clone();
+        let mut cell = CallbackCell::new(move |e: u32| {
+            first.send(("first", e)).unwrap();
+            if e == 2 {
+                let second = first.clone();
+                let handle: SwapHandle<u32> = handle_receiver.recv().unwrap();
+                handle
+                    .swap(move |e| {
+                        second.send(("second", e)).unwrap();
+                        ControlFlow::Continue
+                    })
+                    .unwrap();
+            }
+            ControlFlow::Continue
+        });
+        handle_sender.send(cell.handle()).unwrap();
+        drop(sender);
+
+        MockEventsLoop::new(vec![1, 2, 3]).run_swappable(&mut cell);
+        drop(cell);
+        let delivered = receiver.iter().collect::<Vec<_>>();
+        assert_eq!(delivered, vec![("first", 1), ("first", 2), ("second", 3)]);
+    }
+
+    #[test]
+    fn swaps_sent_from_another_thread_keep_the_last_one() {
+        let mut cell = CallbackCell::new(|_: u32| ControlFlow::Continue);
+        let handle = cell.handle();
+        thread::spawn(move || {
+            handle.swap(|_| ControlFlow::ExitWithCode(1)).unwrap();
+            handle.swap(|_| ControlFlow::ExitWithCode(2)).unwrap();
+        })
+        .join()
+        .unwrap();
+
+        let mut l = MockEventsLoop::new(vec![1, 2]);
+        assert_eq!(l.try_run::<()>(&mut |e| Ok(cell.call(e))), Ok(2));
+
+        let handle = cell.handle();
+        drop(cell);
+        assert!(handle.swap(|_| ControlFlow::Continue).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Changing the interpretation of raw events as a state machine moves between phases.
+
+use {ControlFlow, EventsLoop, EventsLoopProxy};
+
+type Step<'a, S, Raw, Event> = Box<dyn FnMut(&mut S, Raw) -> Option<Event> + 'a>;
+
+/// Wraps an `EventsLoop` of raw events and interprets each of them according to a state.
+///
+/// The step function receives the current state and a raw event, and returns the event to
+/// deliver, or `None` to swallow it. It can also update the state, typically to move to the next
+/// phase of a protocol, so that the following raw events are mapped or filtered differently.
+/// Unlike switching from one loop to another, the source of the events stays the same and only
+/// their interpretation changes.
+///
+/// The state can be inspected with `current_state` between calls to `poll_events` and `run`.
+pub struct Transition<'a, L, S, Raw, Event> {
+    inner: L,
+    state: S,
+    step: Step<'a, S, Raw, Event>,
+}
+
+impl<'a, L, S, Raw, Event> Transition<'a, L, S, Raw, Event>
+where
+    L: EventsLoop<Raw>,
+{
+    /// Builds the adapter around `inner`, starting in `initial` state.
+    pub fn new<F>(inner: L, initial: S, step: F) -> Transition<'a, L, S, Raw, Event>
+    where
+        F: FnMut(&mut S, Raw) -> Option<Event> + 'a,
+    {
+        Transition {
+            inner,
+            state: initial,
+            step: Box::new(step),
+        }
+    }
+
+    /// Returns the current state.
+    pub fn current_state(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns the wrapped loop and the final state.
+    pub fn into_inner(self) -> (L, S) {
+        (self.inner, self.state)
+    }
+}
+
+impl<'a, L, S, Raw, Event> EventsLoop<Event> for Transition<'a, L, S, Raw, Event>
+where
+    L: EventsLoop<Raw>,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let Transition {
+            ref mut inner,
+            ref mut state,
+            ref mut step,
+        } = *self;
+        inner.poll_events(&mut |raw| {
+            if let Some(event) = step(state, raw) {
+                callback(event);
+            }
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let Transition {
+            ref mut inner,
+            ref mut state,
+            ref mut step,
+        } = *self;
+        inner.run(&mut |raw| match step(state, raw) {
+            Some(event) => callback(event),
+            None => ControlFlow::Continue,
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Transition;
+    use mock::MockEventsLoop;
+    use {ControlFlow, EventsLoop};
+
+    #[derive(Debug, PartialEq)]
+    enum Phase {
+        Handshake { greetings: u32 },
+        Data,
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Message {
+        Connected,
+        Payload(u8),
+    }
+
+    // Bytes are greetings until a zero completes the handshake, and payloads afterwards.
+    fn protocol(phase: &mut Phase, byte: u8) -> Option<Message> {
+        match *phase {
+            Phase::Handshake { ref mut greetings } if byte != 0 => {
+                *greetings += 1;
+                None
+            }
+            Phase::Handshake { .. } => {
+                *phase = Phase::Data;
+                Some(Message::Connected)
+            }
+            Phase::Data => Some(Message::Payload(byte)),
+        }
+    }
+
+    #[test]
+    fn interpretation_changes_with_the_phase() {
+        let mut l = Transition::new(
+            MockEventsLoop::new(vec![7, 7, 0, 1, 0]),
+            Phase::Handshake { greetings: 0 },
+            protocol,
+        );
+
+        let mut delivered = Vec::new();
+        l.run(&mut |message| {
+            delivered.push(message);
+            ControlFlow::Break
+        });
+        assert_eq!(delivered, vec![Message::Connected]);
+        assert_eq!(*l.current_state(), Phase::Data);
+
+        l.poll_events(&mut |message| delivered.push(message));
+        assert_eq!(
+            delivered,
+            vec![Message::Connected, Message::Payload(1), Message::Payload(0)]
+        );
+    }
+
+    #[test]
+    fn state_is_visible_between_calls() {
+        let mut l = Transition::new(
+            MockEventsLoop::new(vec![3, 3]),
+            Phase::Handshake { greetings: 0 },
+            protocol,
+        );
+        l.poll_events(&mut |_| panic!("no message before the handshake completes"));
+        assert_eq!(*l.current_state(), Phase::Handshake { greetings: 2 });
+    }
+}
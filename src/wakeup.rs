@@ -0,0 +1,99 @@
+//! Turning bare wakeups into meaningful events.
+
+use {ControlFlow, EventsLoop, EventsLoopProxy, FromWakeup};
+
+/// Wraps an `EventsLoop` and replaces the events produced by `EventsLoopProxy::wakeup` with the
+/// result of a closure. See `EventsLoop::on_wakeup`.
+pub struct OnWakeup<L, F> {
+    inner: L,
+    on_wakeup: F,
+}
+
+impl<L, F> OnWakeup<L, F> {
+    /// Builds the adapter around `inner`, replacing its wakeup events with `on_wakeup()`.
+    pub fn new(inner: L, on_wakeup: F) -> OnWakeup<L, F> {
+        OnWakeup { inner, on_wakeup }
+    }
+
+    /// Returns the wrapped loop.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+impl<L, F, Event> EventsLoop<Event> for OnWakeup<L, F>
+where
+    L: EventsLoop<Event>,
+    F: FnMut() -> Event,
+    Event: FromWakeup,
+{
+    fn poll_events(&mut self, callback: &mut dyn FnMut(Event)) {
+        let on_wakeup = &mut self.on_wakeup;
+        self.inner.poll_events(&mut |event| {
+            if event.is_wakeup() {
+                callback(on_wakeup());
+            } else {
+                callback(event);
+            }
+        });
+    }
+
+    fn run(&mut self, callback: &mut dyn FnMut(Event) -> ControlFlow) {
+        let on_wakeup = &mut self.on_wakeup;
+        self.inner.run(&mut |event| {
+            if event.is_wakeup() {
+                callback(on_wakeup())
+            } else {
+                callback(event)
+            }
+        });
+    }
+
+    fn create_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_proxy()
+    }
+
+    fn create_wakeup_proxy(&self) -> Box<dyn EventsLoopProxy> {
+        self.inner.create_wakeup_proxy()
+    }
+
+    fn remaining_capacity(&self) -> Option<usize> {
+        self.inner.remaining_capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn is_running(&self) -> bool {
+        self.inner.is_running()
+    }
+
+    fn pending_count(&self) -> Option<usize> {
+        self.inner.pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use channel::ChannelEventsLoop;
+    use mock::Event;
+    use {EventsLoop, EventsLoopPayloadProxy, EventsLoopProxy};
+
+    #[test]
+    fn bare_wakeup_surfaces_as_the_custom_event() {
+        let l = ChannelEventsLoop::new();
+        let proxy = l.create_payload_proxy();
+        proxy.wakeup_with(Event::Data(1)).unwrap();
+        proxy.wakeup().unwrap();
+        proxy.wakeup_with(Event::Data(2)).unwrap();
+
+        let mut l = l.on_wakeup(|| Event::Data(99));
+        let mut delivered = Vec::new();
+        l.poll_events(&mut |e| delivered.push(e));
+        assert_eq!(
+            delivered,
+            vec![Event::Data(1), Event::Data(99), Event::Data(2)]
+        );
+    }
+}